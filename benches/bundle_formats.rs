@@ -0,0 +1,47 @@
+//! Bundle format benchmark: JSON vs binary size and encode/decode time
+//! Run with: cargo bench --bench bundle_formats
+
+use hybrid_phi::phi_bundle::PhiBundle;
+use hybrid_phi::phi_meta::PhiMetadata;
+use hybrid_phi::quantized_memory::phi_quantized_encode;
+use hybrid_phi::signal::generate_sine_wave;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const ROUNDS: u32 = 20;
+
+fn time<T>(mut f: impl FnMut() -> T) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        black_box(f());
+    }
+    start.elapsed() / ROUNDS
+}
+
+fn main() {
+    for len in [1_000, 100_000, 1_000_000] {
+        let data: Vec<f64> = generate_sine_wave(len, 5.0, 0.0)
+            .iter()
+            .map(|&x| phi_quantized_encode(x, 10, 0.01))
+            .collect();
+        let bundle = PhiBundle::new("bench", PhiMetadata::new(10, 0.01, len), data);
+
+        let json = serde_json::to_vec_pretty(&bundle).unwrap();
+        let binary = bundle.to_bytes().unwrap();
+
+        let json_encode = time(|| serde_json::to_vec_pretty(&bundle).unwrap());
+        let json_decode = time(|| serde_json::from_slice::<PhiBundle>(&json).unwrap());
+        let binary_encode = time(|| bundle.to_bytes().unwrap());
+        let binary_decode = time(|| PhiBundle::from_bytes(&binary).unwrap());
+
+        println!("{} values", len);
+        println!(
+            "  json   {:>10} bytes  encode {:>10.3?}  decode {:>10.3?}",
+            json.len(), json_encode, json_decode
+        );
+        println!(
+            "  binary {:>10} bytes  encode {:>10.3?}  decode {:>10.3?}",
+            binary.len(), binary_encode, binary_decode
+        );
+    }
+}
@@ -0,0 +1,68 @@
+//! Routing benchmark: exact scan vs LSH candidates, recall@1 and query latency
+//! Run with: cargo bench --bench routing_ann
+
+use hybrid_phi::phi_lsh::LshConfig;
+use hybrid_phi::phi_metric::Tolerance;
+use hybrid_phi::phi_router::PhiRouter;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::hint::black_box;
+use std::time::Instant;
+
+const PATTERNS: usize = 20_000;
+const LENGTH: usize = 32;
+const QUERIES: usize = 200;
+/// Positions of each query replaced by noise
+const NOISY: usize = 3;
+const STEP: f64 = 0.01;
+
+fn router(patterns: &[Vec<f64>]) -> PhiRouter {
+    let mut router = PhiRouter::new().with_metric(Tolerance::default());
+    for (i, data) in patterns.iter().enumerate() {
+        router.insert(&format!("p{:05}", i), data.clone(), Some(STEP));
+    }
+    router
+}
+
+fn main() {
+    let mut rng = StdRng::seed_from_u64(7);
+    let code = |rng: &mut StdRng| rng.gen_range(0..200) as f64 * STEP;
+    let patterns: Vec<Vec<f64>> = (0..PATTERNS).map(|_| (0..LENGTH).map(|_| code(&mut rng)).collect()).collect();
+    let queries: Vec<Vec<f64>> = (0..QUERIES)
+        .map(|_| {
+            let mut query = patterns[rng.gen_range(0..PATTERNS)].clone();
+            for _ in 0..NOISY {
+                let at = rng.gen_range(0..LENGTH);
+                query[at] = code(&mut rng);
+            }
+            query
+        })
+        .collect();
+
+    let exact = router(&patterns);
+    let start = Instant::now();
+    let truth: Vec<Option<String>> = queries.iter().map(|q| exact.route(q, 0.5).map(|r| r.0)).collect();
+    let exact_time = start.elapsed() / QUERIES as u32;
+    println!("{} patterns of {} values, {} queries with {} noisy positions", PATTERNS, LENGTH, QUERIES, NOISY);
+    println!("  exact scan                   recall 1.000  query {:>10.3?}", exact_time);
+
+    for (tables, positions) in [(4, 4), (8, 4), (16, 4), (8, 8), (16, 8)] {
+        let config = LshConfig { tables, positions, ..LshConfig::default() };
+        let start = Instant::now();
+        let approx = router(&patterns).with_lsh(config);
+        let build = start.elapsed();
+
+        let start = Instant::now();
+        let found: Vec<Option<String>> = queries.iter().map(|q| black_box(approx.route(q, 0.5)).map(|r| r.0)).collect();
+        let approx_time = start.elapsed() / QUERIES as u32;
+        let hits = found.iter().zip(&truth).filter(|(f, t)| f == t).count();
+        println!(
+            "  lsh {:>2} tables x {} positions  recall {:.3}  query {:>10.3?}  build {:>10.3?}",
+            tables,
+            positions,
+            hits as f64 / QUERIES as f64,
+            approx_time,
+            build
+        );
+    }
+}
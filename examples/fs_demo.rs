@@ -11,6 +11,7 @@ use std::path::Path;
 fn main() {
     let n = 10;
     let step = 0.01;
+    #[allow(clippy::approx_constant)]
     let values = [1.0, 2.0, 3.14, 42.0];
     let name = "phi_shape";
     let store = PhiMemoryStore::new(".phi_store");
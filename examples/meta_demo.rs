@@ -1,8 +1,7 @@
 //! CLI demo for phi_meta: save/load φ-metadata
 //! Run with: cargo run --example meta_demo
 
-use hybrid_phi::phi_meta::PhiMetadata;
-use chrono::Utc;
+use hybrid_phi::phi_meta::{PhiEncoding, PhiMetadata};
 use std::path::Path;
 
 fn main() {
@@ -14,12 +13,11 @@ fn main() {
 
 
     // Create metadata
-    let meta = PhiMetadata {
-        n: 10,
-        step: 0.01,
-        length: 4,
-        saved_at: Utc::now(),
-    };
+    let mut meta = PhiMetadata::new(10, 0.01, 4);
+    meta.sample_rate = Some(100.0);
+    meta.unit = Some("V".to_string());
+    meta.encoding = Some(PhiEncoding::PhiQuantized);
+    meta.tags.insert("sensor".to_string(), "left".to_string());
 
     // Save metadata
     meta.save(name, store_path).expect("failed to save metadata");
@@ -32,6 +30,10 @@ fn main() {
     println!("  step     = {:.5}", loaded.step);
     println!("  length   = {}", loaded.length);
     println!("  saved_at = {}", loaded.saved_at);
+    println!("  rate     = {:?}", loaded.sample_rate);
+    println!("  unit     = {:?}", loaded.unit);
+    println!("  encoding = {:?}", loaded.encoding);
+    println!("  tags     = {:?}", loaded.tags);
 
     // Optional cleanup
     let _ = std::fs::remove_file(store_path.join("example_phi.meta.txt"));
@@ -1,19 +1,30 @@
 //! phi_app: unified CLI tool for encoding, storing, routing φ-signals
-//! Run with: cargo run --example phi_app encode <name>
-//!         : cargo run --example phi_app route --input=... --threshold=0.9
+//! Run with: cargo run --example phi_app encode <name> [--unit=mV] [--rate=250] [--tag=key=value]
+//!         : cargo run --example phi_app route --input=... --threshold=0.9 [--metric=tolerance|hamming|cosine|pearson|euclidean|l1|dtw|sliding]
 //!         : cargo run --example phi_app list
+//!         : cargo run --example phi_app reindex
+//!         : cargo run --example phi_app migrate-meta --to=json|toml|text
+//!         : cargo run --example phi_app upgrade-meta
+//!         : cargo run --example phi_app gc [--max-entries=N] [--max-bytes=N] [--max-age-days=N]
 //!         : cargo run --example phi_app delete <name>
 //!         : cargo run --example phi_app describe <name>
-//!         : cargo run --example phi_app export <name> --to=file.json
-//!         : cargo run --example phi_app import <name> --from=file.json
+//!         : cargo run --example phi_app decode <name>
+//!         : cargo run --example phi_app export <name> --to=file.json|file.phib [--stream]
+//!         : cargo run --example phi_app import <name> --from=file.json|file.phib [--on-conflict=reject|overwrite|rename|skip]
+//!         : cargo run --example phi_app archive --to=file.phia [--prefix=..] [--tag=key=value]
+//!         : cargo run --example phi_app unarchive --from=file.phia [<name>]
 
 use hybrid_phi::quantized_memory::phi_quantized_encode;
+use hybrid_phi::phi_spec::EncodingSpec;
 use hybrid_phi::phi_fs::PhiMemoryStore;
-use hybrid_phi::phi_meta::PhiMetadata;
-use hybrid_phi::phi_bundle::PhiBundle;
-use hybrid_phi::phi_router::{phi_similarity, phi_route};
+use hybrid_phi::phi_index::phi_checksum;
+use hybrid_phi::phi_meta::{MetaFormat, PhiMetadata};
+use hybrid_phi::phi_bundle::{ImportOutcome, ImportPolicy, PhiBundle};
+use hybrid_phi::phi_archive::{ArchiveFilter, PhiArchive};
+use hybrid_phi::phi_retention::RetentionPolicy;
+use hybrid_phi::phi_metric::{metric_from_name, SimilarityMetric, Tolerance, EXACT_TOLERANCE};
+use hybrid_phi::phi_router::{phi_explain_with, phi_route_top_k_with};
 use std::env;
-use std::fs;
 
 fn parse_input_vec(arg: &str) -> Vec<f64> {
     arg.split(',').filter_map(|s| s.parse().ok()).collect()
@@ -22,7 +33,7 @@ fn parse_input_vec(arg: &str) -> Vec<f64> {
 fn main() {
     let args: Vec<String> = env::args().collect();
     if args.len() < 2 {
-        eprintln!("Usage:\n  encode <name>\n  route --input=... [--threshold=0.9] [--verbose]\n  list\n  delete <name>\n  describe <name>\n  export <name> --to=file.json\n  import <name> --from=file.json");
+        eprintln!("Usage:\n  encode <name> [--unit=..] [--rate=..] [--tag=key=value]\n  route --input=... [--threshold=0.9] [--metric=..] [--verbose]\n  list\n  reindex\n  migrate-meta --to=json|toml|text\n  upgrade-meta\n  gc [--max-entries=N] [--max-bytes=N] [--max-age-days=N]\n  delete <name>\n  describe <name>\n  decode <name>\n  export <name> --to=file.json|file.phib [--stream]\n  import <name> --from=file.json|file.phib [--on-conflict=reject|overwrite|rename|skip]\n  archive --to=file.phia [--prefix=..] [--tag=key=value]\n  unarchive --from=file.phia [<name>]");
         return;
     }
 
@@ -37,15 +48,23 @@ fn main() {
         let mut buf = String::new();
         std::io::stdin().read_line(&mut buf).unwrap();
         let signal = parse_input_vec(&buf);
-        let encoded: Vec<f64> = signal.iter().map(|&x| phi_quantized_encode(x, n, step)).collect();
+        let spec = EncodingSpec::phi_quantized(n, step);
+        let encoded = spec.encode(&signal);
         store.save(name, &encoded).expect("failed to save");
-        let meta = PhiMetadata {
-            n,
-            step,
-            length: encoded.len(),
-            saved_at: chrono::Utc::now(),
-        };
-        meta.save(name, ".phi_store").expect("failed to save metadata");
+        let mut meta = PhiMetadata::for_spec(&spec, encoded.len());
+        meta.checksum = Some(phi_checksum(&encoded));
+        for arg in &args[3..] {
+            if let Some(v) = arg.strip_prefix("--unit=") {
+                meta.unit = Some(v.to_string());
+            }
+            if let Some(v) = arg.strip_prefix("--rate=") {
+                meta.sample_rate = v.parse().ok();
+            }
+            if let Some((key, value)) = arg.strip_prefix("--tag=").and_then(|kv| kv.split_once('=')) {
+                meta.tags.insert(key.to_string(), value.to_string());
+            }
+        }
+        store.save_meta(name, &meta).expect("failed to save metadata");
         println!("Saved {} values to '{}'.", encoded.len(), name);
         return;
     }
@@ -54,6 +73,7 @@ fn main() {
         let mut input: Option<Vec<f64>> = None;
         let mut threshold = 0.8;
         let mut verbose = false;
+        let mut metric: Box<dyn SimilarityMetric> = Box::new(Tolerance::new(EXACT_TOLERANCE));
 
         for arg in &args[2..] {
             if let Some(v) = arg.strip_prefix("--input=") {
@@ -62,6 +82,9 @@ fn main() {
             if let Some(v) = arg.strip_prefix("--threshold=") {
                 threshold = v.parse().unwrap_or(threshold);
             }
+            if let Some(v) = arg.strip_prefix("--metric=") {
+                metric = metric_from_name(v).unwrap_or_else(|| panic!("Unknown --metric value '{}'", v));
+            }
             if arg == "--verbose" {
                 verbose = true;
             }
@@ -70,49 +93,153 @@ fn main() {
         let input = input.expect("Missing --input argument");
         let encoded_input: Vec<f64> = input.iter().map(|&x| phi_quantized_encode(x, n, step)).collect();
 
+        // Rank everything once; the winner is the top entry if it clears the threshold
+        let ranked = phi_route_top_k_with(&encoded_input, &store, usize::MAX, 0.0, metric.as_ref());
         if verbose {
-            println!("Similarity to each stored φ-memory:");
-            for name in store.list().unwrap_or_default() {
-                if let Ok(entry) = store.load(&name) {
-                    let score = phi_similarity(&encoded_input, &entry);
-                    println!("- {}: {:.3}%", name, score * 100.0);
-                }
+            println!("Similarity ({}) to each stored φ-memory:", metric.name());
+            for candidate in &ranked {
+                println!("- {}: {:.3}%", candidate.name, candidate.score * 100.0);
             }
         }
 
-        match phi_route(&encoded_input, &store, threshold) {
-            Some((name, score)) => println!("\nInput routed to '{}', score = {:.3}%", name, score * 100.0),
+        match ranked.first().filter(|c| c.score > 0.0 && c.score >= threshold) {
+            Some(best) => {
+                println!("\nInput routed to '{}', score = {:.3}%", best.name, best.score * 100.0);
+                if verbose {
+                    if let Ok(why) = phi_explain_with(&encoded_input, &store, &best.name, metric.as_ref()) {
+                        if let Some(offset) = why.offset.filter(|o| *o != 0) {
+                            println!("  aligned at stored offset {}", offset);
+                        }
+                        match why.tolerance {
+                            Some(tolerance) => println!(
+                                "  {}/{} positions within ±{} (input length {}, stored length {}), max deviation {:.4}",
+                                why.matched.len(),
+                                why.deviations.len(),
+                                tolerance,
+                                why.input_len,
+                                why.stored_len,
+                                why.max_deviation()
+                            ),
+                            None if why.offset.is_none() => println!(
+                                "  {} score {:.4} over a warped alignment (input length {}, stored length {})",
+                                why.metric,
+                                why.score,
+                                why.input_len,
+                                why.stored_len
+                            ),
+                            None => println!(
+                                "  {} score {:.4} (input length {}, stored length {}), max deviation {:.4}",
+                                why.metric,
+                                why.score,
+                                why.input_len,
+                                why.stored_len,
+                                why.max_deviation()
+                            ),
+                        }
+                    }
+                }
+            }
             None => println!("\nNo route found (threshold = {:.2})", threshold),
         }
         return;
     }
 
     if mode == "list" {
-        let entries = store.list().unwrap_or_default();
+        let manifest = store.manifest().expect("failed to read manifest");
         println!("Stored φ-memories:");
-        for name in entries {
-            println!("- {}", name);
+        for entry in manifest.entries.values() {
+            match entry.n {
+                Some(n) => println!("- {} (length = {}, n = {})", entry.name, entry.length, n),
+                None => println!("- {} (length = {})", entry.name, entry.length),
+            }
         }
         return;
     }
 
+    if mode == "reindex" {
+        let manifest = store.reindex().expect("failed to rebuild manifest");
+        println!("Reindexed {} φ-memories.", manifest.entries.len());
+        return;
+    }
+
+    if mode == "migrate-meta" {
+        let format = args[2..]
+            .iter()
+            .find_map(|arg| arg.strip_prefix("--to="))
+            .and_then(MetaFormat::parse)
+            .expect("Missing or unknown --to=json|toml|text argument");
+        match store.migrate_metadata(format) {
+            Ok(names) => println!("Migrated metadata of {} φ-memories to {:?}.", names.len(), format),
+            Err(err) => println!("Migration failed: {}", err),
+        }
+        return;
+    }
+
+    if mode == "upgrade-meta" {
+        match store.upgrade_metadata() {
+            Ok(names) => println!("Upgraded metadata schema of {} φ-memories.", names.len()),
+            Err(err) => println!("Upgrade failed: {}", err),
+        }
+        return;
+    }
+
+    if mode == "gc" {
+        let mut policy = RetentionPolicy::default();
+        for arg in &args[2..] {
+            if let Some(v) = arg.strip_prefix("--max-entries=") {
+                policy.max_entries = v.parse().ok();
+            }
+            if let Some(v) = arg.strip_prefix("--max-bytes=") {
+                policy.max_bytes = v.parse().ok();
+            }
+            if let Some(v) = arg.strip_prefix("--max-age-days=") {
+                policy.max_age = v.parse().ok().map(chrono::Duration::days);
+            }
+        }
+        let report = store.gc(&policy).expect("failed to collect garbage");
+        println!(
+            "Expired {}, evicted {}, removed {} orphaned files, freed {} bytes.",
+            report.expired.len(),
+            report.evicted.len(),
+            report.orphans_removed.len(),
+            report.bytes_freed
+        );
+        return;
+    }
+
     if mode == "delete" && args.len() >= 3 {
         let name = &args[2];
-        let _ = fs::remove_file(format!(".phi_store/{}.bin", name));
-        let _ = fs::remove_file(format!(".phi_store/{}.meta.txt", name));
-        println!("Deleted memory '{}'.", name);
+        match store.delete(name) {
+            Ok(()) => println!("Deleted memory '{}'.", name),
+            Err(err) => println!("Failed to delete '{}': {}", name, err),
+        }
         return;
     }
 
     if mode == "describe" && args.len() >= 3 {
         let name = &args[2];
-        match PhiMetadata::load(name, ".phi_store") {
+        match store.load_meta(name) {
             Ok(meta) => {
                 println!("φ-memory '{}':", name);
                 println!("  length   = {}", meta.length);
                 println!("  n        = {}", meta.n);
                 println!("  step     = {:.5}", meta.step);
                 println!("  saved_at = {}", meta.saved_at);
+                if let Some(rate) = meta.sample_rate {
+                    println!("  rate     = {} Hz", rate);
+                }
+                if let Some(unit) = &meta.unit {
+                    println!("  unit     = {}", unit);
+                }
+                if let Some(source) = &meta.source {
+                    println!("  source   = {}", source);
+                }
+                if let Some(spec) = meta.encoding_spec() {
+                    println!("  encoding = {} (table v{})", spec.codec.as_str(), spec.table_version);
+                }
+                for (key, value) in &meta.tags {
+                    println!("  tag {} = {}", key, value);
+                }
             }
             Err(err) => {
                 println!("Failed to load metadata: {}", err);
@@ -121,17 +248,41 @@ fn main() {
         return;
     }
 
+    if mode == "decode" && args.len() >= 3 {
+        let name = &args[2];
+        match store.load_decoded(name) {
+            Ok(values) => {
+                let text: Vec<String> = values.iter().map(|v| format!("{:.6}", v)).collect();
+                println!("{}", text.join(","));
+            }
+            Err(err) => println!("Failed to decode '{}': {}", name, err),
+        }
+        return;
+    }
+
     if mode == "export" && args.len() >= 4 {
         let name = &args[2];
         let mut out_path = None;
+        let mut stream = false;
         for arg in &args[3..] {
             if let Some(p) = arg.strip_prefix("--to=") {
                 out_path = Some(p);
+            } else if arg == "--stream" {
+                stream = true;
             }
         }
         let out_path = out_path.expect("Missing --to=... argument");
+        if stream {
+            store.export_bundle_stream(name, out_path).expect("failed to stream binary bundle");
+            println!("Streamed '{}' to '{}'.", name, out_path);
+            return;
+        }
         let bundle = PhiBundle::from_store(name, &store).expect("failed to bundle");
-        bundle.save_json(out_path).expect("failed to save json");
+        if out_path.ends_with(".phib") {
+            bundle.save_binary(out_path).expect("failed to save binary bundle");
+        } else {
+            bundle.save_json(out_path).expect("failed to save json");
+        }
         println!("Exported '{}' to '{}'.", name, out_path);
         return;
     }
@@ -139,17 +290,74 @@ fn main() {
     if mode == "import" && args.len() >= 4 {
         let name = &args[2];
         let mut in_path = None;
+        let mut policy = ImportPolicy::Reject;
         for arg in &args[3..] {
             if let Some(p) = arg.strip_prefix("--from=") {
                 in_path = Some(p);
+            } else if let Some(p) = arg.strip_prefix("--on-conflict=") {
+                policy = match p {
+                    "reject" => ImportPolicy::Reject,
+                    "overwrite" => ImportPolicy::Overwrite,
+                    "rename" => ImportPolicy::Rename,
+                    "skip" => ImportPolicy::Skip,
+                    other => panic!("Unknown --on-conflict value '{}'", other),
+                };
             }
         }
         let in_path = in_path.expect("Missing --from=... argument");
-        let bundle = PhiBundle::load_json(in_path).expect("failed to load json");
-        bundle.save_to_store(&store).expect("failed to restore");
-        println!("Imported '{}' from '{}'.", name, in_path);
+        let mut bundle = PhiBundle::load(in_path).expect("failed to load bundle");
+        bundle.name = name.clone();
+        let report = bundle.validate();
+        for issue in &report.issues {
+            println!("  ✗ {}", issue);
+        }
+        match bundle.import_into(&store, policy) {
+            Ok(ImportOutcome::Imported(stored)) => println!("Imported '{}' from '{}'.", stored, in_path),
+            Ok(ImportOutcome::Skipped) => println!("'{}' already exists, skipped.", name),
+            Err(err) => println!("Import failed: {}", err),
+        }
+        return;
+    }
+
+    if mode == "archive" {
+        let mut out_path = None;
+        let mut filter = ArchiveFilter::default();
+        for arg in &args[2..] {
+            if let Some(p) = arg.strip_prefix("--to=") {
+                out_path = Some(p);
+            } else if let Some(p) = arg.strip_prefix("--prefix=") {
+                filter = filter.with_prefix(p);
+            } else if let Some((k, v)) = arg.strip_prefix("--tag=").and_then(|kv| kv.split_once('=')) {
+                filter = filter.with_tag(k, v);
+            }
+        }
+        let out_path = out_path.expect("Missing --to=... argument");
+        let archive = PhiArchive::export(&store, out_path, &filter).expect("failed to archive");
+        println!("Archived {} φ-memories to '{}'.", archive.entries().len(), out_path);
+        return;
+    }
+
+    if mode == "unarchive" {
+        let in_path = args[2..]
+            .iter()
+            .find_map(|arg| arg.strip_prefix("--from="))
+            .expect("Missing --from=... argument");
+        let archive = PhiArchive::open(in_path).expect("failed to open archive");
+        match args[2..].iter().find(|arg| !arg.starts_with("--")) {
+            Some(name) => {
+                archive.extract(name).and_then(|b| b.save_to_store(&store)).expect("failed to extract");
+                println!("Extracted '{}' from '{}'.", name, in_path);
+            }
+            None => {
+                for entry in archive.entries() {
+                    println!("{:<20} {:>8} values", entry.name, entry.length);
+                }
+                let names = archive.extract_all(&store).expect("failed to extract");
+                println!("Extracted {} φ-memories from '{}'.", names.len(), in_path);
+            }
+        }
         return;
     }
 
-    eprintln!("Unknown mode '{}'. Use 'encode', 'route', 'list', 'delete', 'describe', 'export', or 'import'", mode);
+    eprintln!("Unknown mode '{}'. Use 'encode', 'route', 'list', 'reindex', 'migrate-meta', 'upgrade-meta', 'gc', 'delete', 'describe', 'decode', 'export', 'import', 'archive' or 'unarchive'", mode);
 }
\ No newline at end of file
@@ -4,6 +4,7 @@
 use hybrid_phi::signal::{generate_sine_wave, phi_encode_signal, phi_decode_signal};
 use plotters::prelude::*;
 
+#[allow(clippy::needless_borrows_for_generic_args)]
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let n = 10;
     let len = 128;
@@ -4,7 +4,7 @@
 
 use hybrid_phi::quantized_memory::phi_quantized_encode;
 use hybrid_phi::phi_fs::PhiMemoryStore;
-use hybrid_phi::phi_router::{phi_route, phi_similarity};
+use hybrid_phi::phi_router::phi_route_top_k;
 use std::env;
 
 fn parse_args() -> (f64, f64, bool) {
@@ -46,27 +46,25 @@ fn main() {
     }
 
     // Simulate input (similar to "burst")
+    #[allow(clippy::useless_vec)]
     let input_signal = vec![0.95, 2.05, 3.1, 3.95];
     let encoded_input: Vec<f64> = input_signal
         .iter()
         .map(|&x| phi_quantized_encode(x, n, step))
         .collect();
 
+    // Rank every pattern once and route to the best one that clears the threshold
+    let ranked = phi_route_top_k(&encoded_input, &store, usize::MAX, 0.0);
     if verbose {
         println!("\nSimilarity to each pattern:");
-        let names = store.list().expect("failed to list");
-        for name in names {
-            if let Ok(entry) = store.load(&name) {
-                let score = phi_similarity(&encoded_input, &entry);
-                println!("- {}: {:.3}%", name, score * 100.0);
-            }
+        for candidate in &ranked {
+            println!("- {}: {:.3}%", candidate.name, candidate.score * 100.0);
         }
     }
 
-    // Route input
-    match phi_route(&encoded_input, &store, threshold) {
-        Some((name, score)) => {
-            println!("\nInput routed to '{}', similarity = {:.3}%", name, score * 100.0);
+    match ranked.first().filter(|c| c.score > 0.0 && c.score >= threshold) {
+        Some(best) => {
+            println!("\nInput routed to '{}', similarity = {:.3}%", best.name, best.score * 100.0);
         }
         None => println!("\nNo matching route found (threshold = {:.2})", threshold),
     }
@@ -6,6 +6,7 @@ use hybrid_phi::quantized_memory::phi_quantized_encode;
 use hybrid_phi::storage::{save_quantized, load_quantized};
 use std::path::Path;
 
+#[allow(clippy::needless_borrows_for_generic_args)]
 fn main() {
     let values = [-1000.0, -1.0, 0.0, 1.0, 42.0, 123.456, 999.99];
     let n = 10;
@@ -4,7 +4,21 @@ pub mod signal;
 pub mod phi_codec;
 pub mod quantized_memory;
 pub mod storage;
+pub mod phi_error;
 pub mod phi_fs;
+pub mod phi_index;
+pub mod phi_retention;
+pub mod phi_versions;
+pub mod phi_dedup;
+pub mod phi_compress;
+pub mod phi_crypto;
+pub mod phi_sign;
 pub mod phi_meta;
+pub mod phi_spec;
+pub mod phi_schema;
+pub mod phi_metric;
+pub mod phi_lsh;
 pub mod phi_router;
-pub mod phi_bundle;
\ No newline at end of file
+pub mod phi_bundle;
+pub mod phi_stream;
+pub mod phi_archive;
\ No newline at end of file
@@ -0,0 +1,393 @@
+//! phi_archive: many φ-memories packed into one portable file
+//! Entries are binary bundles followed by a table of contents, so single entries can be read on their own
+
+use crate::phi_bundle::PhiBundle;
+use crate::phi_error::{PhiError, PhiResult};
+use crate::phi_fs::PhiMemoryStore;
+use crate::phi_meta::PhiMetadata;
+#[cfg(feature = "signing")]
+use crate::phi_sign::{signature_path, BundleSignature, PhiSigner, TrustedKeys};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Magic bytes opening and closing every archive
+pub const ARCHIVE_MAGIC: &[u8; 4] = b"PHIA";
+const ARCHIVE_VERSION: u8 = 1;
+const HEADER_LEN: u64 = 5;
+/// TOC offset (u64), TOC length (u64), magic
+const FOOTER_LEN: u64 = 20;
+/// Prefix of every archive signature's message, ahead of the file bytes
+#[cfg(feature = "signing")]
+const ARCHIVE_SIGNING_DOMAIN: &[u8] = b"phi-archive-signature-v1\0";
+
+/// Which store entries `PhiArchive::export` packs; the default takes all of them
+#[derive(Debug, Clone, Default)]
+pub struct ArchiveFilter {
+    /// Only names starting with this prefix
+    pub prefix: Option<String>,
+    /// Only entries whose metadata carries every one of these tags
+    pub tags: BTreeMap<String, String>,
+}
+
+impl ArchiveFilter {
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.prefix = Some(prefix.to_string());
+        self
+    }
+
+    pub fn with_tag(mut self, key: &str, value: &str) -> Self {
+        self.tags.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    fn matches_name(&self, name: &str) -> bool {
+        self.prefix.as_deref().is_none_or(|p| name.starts_with(p))
+    }
+
+    /// Tag check; entries without metadata match only when no tags are required
+    fn matches_meta(&self, meta: Option<&PhiMetadata>) -> bool {
+        self.tags.iter().all(|(k, v)| meta.and_then(|m| m.tags.get(k)) == Some(v))
+    }
+}
+
+/// Table-of-contents record for one archived entry
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArchiveEntry {
+    pub name: String,
+    /// Byte offset of the entry's binary bundle
+    pub offset: u64,
+    /// Size of the binary bundle in bytes
+    pub size: u64,
+    /// Number of φ-values
+    pub length: usize,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: BTreeMap<String, String>,
+    /// Stored without metadata; its bundle carries a placeholder record (see `placeholder_meta`)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub bare: bool,
+}
+
+/// Stand-in metadata for a bundle whose entry has no sidecar
+///
+/// Only the length is real; `extract_all` restores such entries without metadata.
+pub fn placeholder_meta(length: usize) -> PhiMetadata {
+    PhiMetadata::new(1, 0.0, length)
+}
+
+/// Handle on an archive file whose table of contents has been read
+///
+/// Entries are read through the file handle the archive was opened with, so they come from
+/// the file whose table of contents (and, for `open_verified`, signature) was checked even if
+/// the path is replaced afterwards.
+#[derive(Debug, Clone)]
+pub struct PhiArchive {
+    path: PathBuf,
+    file: Arc<Mutex<File>>,
+    entries: Vec<ArchiveEntry>,
+}
+
+impl PhiArchive {
+    /// Pack the matching entries of a store into an archive file
+    ///
+    /// Entries without metadata are packed as `bare` ones and only match filters without tags.
+    /// Data is read only for entries the filter selects by name and metadata.
+    pub fn export<P: AsRef<Path>>(store: &PhiMemoryStore, path: P, filter: &ArchiveFilter) -> PhiResult<Self> {
+        let names = store.list()?;
+
+        let mut out = BufWriter::new(File::create(path.as_ref())?);
+        out.write_all(ARCHIVE_MAGIC)?;
+        out.write_all(&[ARCHIVE_VERSION])?;
+        let mut offset = HEADER_LEN;
+        let mut entries = Vec::new();
+
+        for name in names {
+            if !filter.matches_name(&name) {
+                continue;
+            }
+            let bare = PhiMetadata::detect_format(&name, store.base_path()).is_none();
+            let meta = match bare {
+                true => None,
+                false => Some(store.read_meta(&name)?),
+            };
+            if !filter.matches_meta(meta.as_ref()) {
+                continue;
+            }
+            let data = store.load(&name)?;
+            let meta = meta.unwrap_or_else(|| placeholder_meta(data.len()));
+            let bundle = PhiBundle::new(&name, meta, data);
+            let bytes = bundle.to_bytes()?;
+            out.write_all(&bytes)?;
+            entries.push(ArchiveEntry {
+                name,
+                offset,
+                size: bytes.len() as u64,
+                length: bundle.data.len(),
+                tags: bundle.metadata.tags,
+                bare,
+            });
+            offset += bytes.len() as u64;
+        }
+
+        let toc = serde_json::to_vec(&entries)?;
+        out.write_all(&toc)?;
+        out.write_all(&offset.to_le_bytes())?;
+        out.write_all(&(toc.len() as u64).to_le_bytes())?;
+        out.write_all(ARCHIVE_MAGIC)?;
+        out.flush()?;
+
+        let file = Arc::new(Mutex::new(File::open(path.as_ref())?));
+        Ok(Self { path: path.as_ref().to_path_buf(), file, entries })
+    }
+
+    /// Open an archive, reading only its header and table of contents
+    pub fn open<P: AsRef<Path>>(path: P) -> PhiResult<Self> {
+        Self::from_file(path.as_ref(), File::open(path.as_ref())?)
+    }
+
+    /// Read the header and table of contents of an archive already opened as `file`
+    fn from_file(path: &Path, mut file: File) -> PhiResult<Self> {
+        file.seek(SeekFrom::Start(0))?;
+        let file_len = file.metadata()?.len();
+        let mut header = [0u8; HEADER_LEN as usize];
+        file.read_exact(&mut header)?;
+        if &header[..4] != ARCHIVE_MAGIC || header[4] != ARCHIVE_VERSION || file_len < HEADER_LEN + FOOTER_LEN {
+            return Err(PhiError::format("not a φ-archive"));
+        }
+
+        let mut footer = [0u8; FOOTER_LEN as usize];
+        file.seek(SeekFrom::End(-(FOOTER_LEN as i64)))?;
+        file.read_exact(&mut footer)?;
+        if &footer[16..] != ARCHIVE_MAGIC {
+            return Err(PhiError::format("φ-archive has no table of contents"));
+        }
+        let toc_offset = u64::from_le_bytes(footer[..8].try_into().unwrap());
+        let toc_len = u64::from_le_bytes(footer[8..16].try_into().unwrap());
+        if toc_offset < HEADER_LEN || toc_offset.checked_add(toc_len) != Some(file_len - FOOTER_LEN) {
+            return Err(PhiError::format("φ-archive table of contents is out of bounds"));
+        }
+
+        let mut toc = vec![0u8; toc_len as usize];
+        file.seek(SeekFrom::Start(toc_offset))?;
+        file.read_exact(&mut toc)?;
+        let entries: Vec<ArchiveEntry> = serde_json::from_slice(&toc)?;
+        if entries.iter().any(|e| e.offset < HEADER_LEN || e.offset.saturating_add(e.size) > toc_offset) {
+            return Err(PhiError::format("φ-archive entry is out of bounds"));
+        }
+        Ok(Self { path: path.to_path_buf(), file: Arc::new(Mutex::new(file)), entries })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Table of contents, in archive order
+    pub fn entries(&self) -> &[ArchiveEntry] {
+        &self.entries
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.entries.iter().map(|e| e.name.clone()).collect()
+    }
+
+    /// Read a single entry, seeking straight to it
+    pub fn extract(&self, name: &str) -> PhiResult<PhiBundle> {
+        let entry = self
+            .entries
+            .iter()
+            .find(|e| e.name == name)
+            .ok_or_else(|| PhiError::NotFound(name.to_string()))?;
+        let mut bytes = vec![0u8; entry.size as usize];
+        {
+            let mut file = self.file.lock().map_err(|_| PhiError::format("φ-archive handle was poisoned"))?;
+            file.seek(SeekFrom::Start(entry.offset))?;
+            file.read_exact(&mut bytes)?;
+        }
+        let bundle = PhiBundle::from_bytes(&bytes)?;
+        if bundle.name != entry.name {
+            return Err(PhiError::Validation(format!(
+                "archive entry '{}' holds a bundle named '{}'",
+                entry.name, bundle.name
+            )));
+        }
+        Ok(bundle)
+    }
+
+    /// Write a detached signature over the whole archive file; returns its path
+    #[cfg(feature = "signing")]
+    pub fn sign(&self, signer: &PhiSigner) -> PhiResult<PathBuf> {
+        let sig_path = signature_path(&self.path);
+        signer.sign(&signing_message(&self.path)?).save(&sig_path)?;
+        Ok(sig_path)
+    }
+
+    /// Open an archive only if `<path>.sig` is a trusted signature over it; returns the signer's label
+    #[cfg(feature = "signing")]
+    pub fn open_verified<P: AsRef<Path>>(path: P, trusted: &TrustedKeys) -> PhiResult<(Self, String)> {
+        let signature = BundleSignature::load(signature_path(path.as_ref()))?;
+        let mut stream = trusted.verify_stream(&signature)?;
+        stream.update(ARCHIVE_SIGNING_DOMAIN);
+        // Check the bytes of the very handle the archive is then read through
+        let mut file = File::open(path.as_ref())?;
+        let mut block = vec![0u8; 64 * 1024];
+        loop {
+            let read = file.read(&mut block)?;
+            if read == 0 {
+                break;
+            }
+            stream.update(&block[..read]);
+        }
+        let signer = stream.finish()?;
+        Ok((Self::from_file(path.as_ref(), file)?, signer))
+    }
+
+    /// Restore every archived entry into a store; returns the restored names
+    ///
+    /// Every entry is validated as by `PhiBundle::validate`; bare entries get their data back
+    /// without a metadata sidecar.
+    pub fn extract_all(&self, store: &PhiMemoryStore) -> PhiResult<Vec<String>> {
+        for entry in &self.entries {
+            let bundle = self.extract(&entry.name)?;
+            match entry.bare {
+                true => {
+                    bundle.validate().into_result()?;
+                    store.save(&entry.name, &bundle.data)?;
+                }
+                false => bundle.save_to_store(store)?,
+            }
+        }
+        Ok(self.names())
+    }
+}
+
+/// Bytes covered by an archive signature: a domain tag followed by the whole file
+#[cfg(feature = "signing")]
+fn signing_message(path: &Path) -> PhiResult<Vec<u8>> {
+    let mut message = ARCHIVE_SIGNING_DOMAIN.to_vec();
+    message.extend(std::fs::read(path)?);
+    Ok(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn populate(store: &PhiMemoryStore) {
+        for (name, kind) in [("ecg_a", "ecg"), ("ecg_b", "eeg"), ("emg_a", "ecg")] {
+            store.save(name, &[1.0, 2.0, 3.0]).unwrap();
+            let mut meta = PhiMetadata::new(10, 0.01, 3);
+            meta.tags.insert("kind".to_string(), kind.to_string());
+            store.save_meta(name, &meta).unwrap();
+        }
+        store.save("no_meta", &[4.0]).unwrap();
+    }
+
+    #[test]
+    fn test_archive_export_list_extract() {
+        let store = PhiMemoryStore::new(".phi_archive_store");
+        populate(&store);
+        let path = ".phi_archive_store.phia";
+
+        let archive = PhiArchive::export(&store, path, &ArchiveFilter::default()).unwrap();
+        assert_eq!(archive.names(), vec!["ecg_a", "ecg_b", "emg_a", "no_meta"]);
+        assert!(archive.entries()[3].bare && !archive.entries()[0].bare);
+
+        let opened = PhiArchive::open(path).unwrap();
+        assert_eq!(opened.entries(), archive.entries());
+        assert_eq!(opened.entries()[1].tags["kind"], "eeg");
+        let bundle = opened.extract("emg_a").unwrap();
+        assert_eq!(bundle.data, vec![1.0, 2.0, 3.0]);
+        assert!(opened.extract("missing").is_err());
+
+        let target = PhiMemoryStore::new(".phi_archive_target");
+        assert_eq!(opened.extract_all(&target).unwrap().len(), 4);
+        assert_eq!(target.load("ecg_b").unwrap(), vec![1.0, 2.0, 3.0]);
+        assert_eq!(target.load("no_meta").unwrap(), vec![4.0]);
+        assert_eq!(PhiMetadata::detect_format("no_meta", target.base_path()), None);
+
+        fs::remove_file(path).unwrap();
+        fs::remove_dir_all(".phi_archive_store").unwrap();
+        fs::remove_dir_all(".phi_archive_target").unwrap();
+    }
+
+    /// Archive holding `bundle` under the table-of-contents name `toc_name`, as a bare entry
+    fn hand_built(path: &str, toc_name: &str, bundle: &PhiBundle) {
+        let bytes = bundle.to_bytes().unwrap();
+        let entry = ArchiveEntry {
+            name: toc_name.to_string(),
+            offset: HEADER_LEN,
+            size: bytes.len() as u64,
+            length: bundle.data.len(),
+            tags: BTreeMap::new(),
+            bare: true,
+        };
+        let toc = serde_json::to_vec(&[entry]).unwrap();
+        let mut out = ARCHIVE_MAGIC.to_vec();
+        out.push(ARCHIVE_VERSION);
+        out.extend_from_slice(&bytes);
+        out.extend_from_slice(&toc);
+        out.extend_from_slice(&(HEADER_LEN + bytes.len() as u64).to_le_bytes());
+        out.extend_from_slice(&(toc.len() as u64).to_le_bytes());
+        out.extend_from_slice(ARCHIVE_MAGIC);
+        fs::write(path, out).unwrap();
+    }
+
+    #[test]
+    fn test_extract_validates_bare_and_renamed_entries() {
+        let target = PhiMemoryStore::new(".phi_archive_hostile_target");
+        let path = ".phi_archive_hostile.phia";
+
+        let escape = PhiBundle::new("../../x", placeholder_meta(1), vec![1.0]);
+        hand_built(path, "../../x", &escape);
+        let archive = PhiArchive::open(path).unwrap();
+        assert!(matches!(archive.extract_all(&target), Err(PhiError::Validation(_))));
+        assert!(!Path::new("../x.bin").exists());
+
+        let nan = PhiBundle::new("nan", placeholder_meta(2), vec![f64::NAN]);
+        hand_built(path, "nan", &nan);
+        assert!(matches!(PhiArchive::open(path).unwrap().extract_all(&target), Err(PhiError::Validation(_))));
+
+        // The table of contents cannot relabel a bundle
+        hand_built(path, "innocent", &escape);
+        assert!(matches!(PhiArchive::open(path).unwrap().extract("innocent"), Err(PhiError::Validation(_))));
+        assert!(target.list().unwrap().is_empty());
+
+        fs::remove_file(path).unwrap();
+        fs::remove_dir_all(".phi_archive_hostile_target").unwrap();
+    }
+
+    #[test]
+    fn test_archive_filters_and_corruption() {
+        let store = PhiMemoryStore::new(".phi_archive_filter_store");
+        populate(&store);
+        let path = ".phi_archive_filter_store.phia";
+
+        let filter = ArchiveFilter::default().with_prefix("ecg").with_tag("kind", "ecg");
+        let archive = PhiArchive::export(&store, path, &filter).unwrap();
+        assert_eq!(archive.names(), vec!["ecg_a"]);
+
+        // Entries the filter leaves out are never read, so their corruption does not matter
+        let mut corrupt = b"PHIZ\x01\x03".to_vec();
+        corrupt.extend_from_slice(&8u64.to_le_bytes());
+        corrupt.extend_from_slice(b"\x08\x00\x00\x00garbage!");
+        fs::write(store.base_path().join("emg_a.bin"), corrupt).unwrap();
+        assert!(store.load("emg_a").is_err());
+        let archive = PhiArchive::export(&store, path, &ArchiveFilter::default().with_prefix("ecg")).unwrap();
+        assert_eq!(archive.names(), vec!["ecg_a", "ecg_b"]);
+        let archive = PhiArchive::export(&store, path, &ArchiveFilter::default().with_tag("kind", "eeg")).unwrap();
+        assert_eq!(archive.names(), vec!["ecg_b"]);
+        assert!(PhiArchive::export(&store, path, &ArchiveFilter::default()).is_err());
+
+        let mut bytes = fs::read(path).unwrap();
+        bytes.truncate(bytes.len() - 1);
+        fs::write(path, &bytes).unwrap();
+        assert!(PhiArchive::open(path).is_err());
+
+        fs::remove_file(path).unwrap();
+        fs::remove_dir_all(".phi_archive_filter_store").unwrap();
+    }
+}
@@ -1,47 +1,631 @@
 //! phi_bundle: self-contained φ-memory package (data + metadata)
-//! Supports JSON serialization for portable storage and transmission
+//! Supports JSON and a compact framed binary format for portable storage and transmission
 
+#[cfg(feature = "encryption")]
+use crate::phi_crypto::{open, seal, PhiKey, BUNDLE_AAD};
+use crate::phi_compress::{raw_bytes, raw_values};
+use crate::phi_crypto::is_sealed;
+use crate::phi_error::{PhiError, PhiResult};
 use crate::phi_fs::PhiMemoryStore;
+use crate::phi_index::{phi_checksum, Fnv1a64};
 use crate::phi_meta::PhiMetadata;
+use crate::phi_schema::{upgrade_bundle, BUNDLE_SCHEMA_VERSION};
+use crate::phi_sign::BundleSignature;
+#[cfg(feature = "signing")]
+use crate::phi_sign::{signature_path, PhiSigner, TrustedKeys};
 use serde::{Serialize, Deserialize};
+use std::fmt;
 use std::fs;
+use std::io::{ErrorKind, Read};
 use std::path::Path;
 
+/// Magic bytes opening every binary bundle
+pub const BINARY_MAGIC: &[u8; 4] = b"PHIB";
+pub(crate) const BINARY_VERSION: u8 = 1;
+/// Opens every message a bundle signature covers
+pub(crate) const SIGNING_DOMAIN: &[u8] = b"phi-bundle-signature-v1\0";
+
+/// Checks `PhiBundle::load_json` makes on top of the content hash; the default makes none
+#[derive(Debug, Clone, Default)]
+pub struct LoadOptions {
+    /// Accept only bundles signed by one of these keys, embedded or in `<path>.sig`
+    #[cfg(feature = "signing")]
+    pub trusted: Option<TrustedKeys>,
+}
+
+impl LoadOptions {
+    /// Require a signature by one of `trusted`
+    #[cfg(feature = "signing")]
+    pub fn verified_by(trusted: &TrustedKeys) -> Self {
+        Self { trusted: Some(trusted.clone()) }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PhiBundle {
+    /// Bundle layout version; missing in bundles written before versioning
+    #[serde(default)]
+    pub schema_version: u32,
     pub name: String,
     pub metadata: PhiMetadata,
+    /// `content_hash` computed when the bundle was exported; checked when it is loaded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_hash: Option<String>,
+    /// Embedded signature over everything but the hash and the signature itself
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<BundleSignature>,
     pub data: Vec<f64>,
 }
 
+/// Bundle as written on export, with a freshly computed content hash
+#[derive(Serialize)]
+pub(crate) struct ExportView<'a> {
+    pub(crate) schema_version: u32,
+    pub(crate) name: &'a str,
+    pub(crate) metadata: &'a PhiMetadata,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) content_hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) signature: Option<&'a BundleSignature>,
+    /// Left out of binary headers, which carry the data separately
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) data: Option<&'a [f64]>,
+}
+
+/// Everything but the data of a binary bundle, stored as JSON
+#[derive(Deserialize)]
+pub(crate) struct BinaryHeader {
+    #[serde(default)]
+    pub(crate) schema_version: u32,
+    pub(crate) name: String,
+    pub(crate) metadata: PhiMetadata,
+    #[serde(default)]
+    pub(crate) content_hash: Option<String>,
+    #[serde(default)]
+    pub(crate) signature: Option<BundleSignature>,
+}
+
+/// What to do when an imported bundle's name is already taken in the store
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ImportPolicy {
+    /// Fail with `PhiError::Validation`
+    #[default]
+    Reject,
+    /// Replace the existing entry
+    Overwrite,
+    /// Import under the first free `name-N`
+    Rename,
+    /// Leave the store untouched
+    Skip,
+}
+
+/// Result of `PhiBundle::import_into`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImportOutcome {
+    /// Written under this name
+    Imported(String),
+    /// Name was taken and the policy was `Skip`
+    Skipped,
+}
+
+/// Problem found by `PhiBundle::validate`
+#[derive(Debug, Clone, PartialEq)]
+pub enum BundleIssue {
+    /// Empty, hidden or containing path separators, so unusable as a store name
+    InvalidName { name: String },
+    LengthMismatch { recorded: usize, actual: usize },
+    /// NaN or infinite values; `first` is the index of the first one
+    NonFinite { first: usize, count: usize },
+    InvalidOrder { n: usize },
+    InvalidStep { step: f64 },
+    /// `metadata.checksum` does not match the data
+    ChecksumMismatch { recorded: u64, actual: u64 },
+    /// Recorded encoding spec cannot be decoded by this build
+    UnsupportedEncoding { reason: String },
+}
+
+impl fmt::Display for BundleIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BundleIssue::InvalidName { name } => write!(f, "invalid name '{}'", name),
+            BundleIssue::LengthMismatch { recorded, actual } => {
+                write!(f, "metadata says length={} but the bundle holds {} values", recorded, actual)
+            }
+            BundleIssue::NonFinite { first, count } => {
+                write!(f, "{} non-finite values, first at index {}", count, first)
+            }
+            BundleIssue::InvalidOrder { n } => write!(f, "order n = {} is out of range", n),
+            BundleIssue::InvalidStep { step } => write!(f, "invalid step {}", step),
+            BundleIssue::ChecksumMismatch { recorded, actual } => {
+                write!(f, "checksum {:016x} does not match data ({:016x})", recorded, actual)
+            }
+            BundleIssue::UnsupportedEncoding { reason } => write!(f, "unsupported encoding: {}", reason),
+        }
+    }
+}
+
+/// Every issue `PhiBundle::validate` found; empty means the bundle is safe to import
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BundleReport {
+    pub issues: Vec<BundleIssue>,
+}
+
+impl BundleReport {
+    pub fn is_valid(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// `Ok` if valid, otherwise a `PhiError::Validation` listing every issue
+    pub fn into_result(self) -> PhiResult<()> {
+        if self.is_valid() {
+            return Ok(());
+        }
+        let issues: Vec<String> = self.issues.iter().map(|i| i.to_string()).collect();
+        Err(PhiError::Validation(issues.join("; ")))
+    }
+}
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty() && !name.starts_with('.') && !name.contains(['/', '\\', '\0'])
+}
+
+/// Fill `buf`, treating a short read as a truncated bundle
+pub(crate) fn read_field<R: Read>(reader: &mut R, buf: &mut [u8]) -> PhiResult<()> {
+    reader.read_exact(buf).map_err(|err| match err.kind() {
+        ErrorKind::UnexpectedEof => PhiError::format("truncated binary bundle"),
+        _ => err.into(),
+    })
+}
+
+/// Read a `len`-byte field, allocating only as much as the input actually holds
+fn read_field_vec<R: Read>(reader: &mut R, len: u64) -> PhiResult<Vec<u8>> {
+    let mut buf = Vec::new();
+    reader.take(len).read_to_end(&mut buf)?;
+    if (buf.len() as u64) < len {
+        return Err(PhiError::format("truncated binary bundle"));
+    }
+    Ok(buf)
+}
+
+/// Read a binary bundle up to its payload; returns the header and the payload length in bytes
+pub(crate) fn read_binary_header<R: Read>(reader: &mut R) -> PhiResult<(BinaryHeader, u64)> {
+    let mut prefix = [0u8; 9];
+    read_field(reader, &mut prefix)?;
+    if &prefix[..4] != BINARY_MAGIC {
+        return Err(PhiError::format("not a binary φ-bundle"));
+    }
+    if prefix[4] != BINARY_VERSION {
+        return Err(PhiError::format("unsupported binary bundle version"));
+    }
+    let header = read_field_vec(reader, u32::from_le_bytes(prefix[5..9].try_into().unwrap()) as u64)?;
+    let header: BinaryHeader = serde_json::from_slice(&header)?;
+    let mut payload_len = [0u8; 8];
+    read_field(reader, &mut payload_len)?;
+    Ok((header, u64::from_le_bytes(payload_len)))
+}
+
+/// Encode everything before the payload of a binary bundle
+pub(crate) fn binary_prefix(view: &ExportView<'_>, payload_len: u64) -> PhiResult<Vec<u8>> {
+    let header = serde_json::to_vec(view)?;
+    let mut out = Vec::with_capacity(4 + 1 + 4 + header.len() + 8);
+    out.extend_from_slice(BINARY_MAGIC);
+    out.push(BINARY_VERSION);
+    out.extend_from_slice(&(header.len() as u32).to_le_bytes());
+    out.extend_from_slice(&header);
+    out.extend_from_slice(&payload_len.to_le_bytes());
+    Ok(out)
+}
+
+/// Name and metadata as JSON, each followed by a NUL byte: what `content_hash` and
+/// signatures cover before the data
+pub(crate) fn content_prefix(name: &str, metadata: &PhiMetadata) -> PhiResult<Vec<u8>> {
+    let mut bytes = name.as_bytes().to_vec();
+    bytes.push(0);
+    bytes.extend(serde_json::to_vec(metadata)?);
+    bytes.push(0);
+    Ok(bytes)
+}
+
+/// Hasher fed with everything `content_hash` covers except the data
+pub(crate) fn content_hasher(name: &str, metadata: &PhiMetadata) -> PhiResult<Fnv1a64> {
+    let mut hasher = Fnv1a64::new();
+    hasher.update(&content_prefix(name, metadata)?);
+    Ok(hasher)
+}
+
+/// Checks that need only the name and metadata of a bundle
+pub(crate) fn header_issues(name: &str, meta: &PhiMetadata) -> Vec<BundleIssue> {
+    let mut issues = Vec::new();
+    if !valid_name(name) {
+        issues.push(BundleIssue::InvalidName { name: name.to_string() });
+    }
+    if PhiError::check_order(meta.n).is_err() {
+        issues.push(BundleIssue::InvalidOrder { n: meta.n });
+    }
+    if !meta.step.is_finite() || meta.step < 0.0 {
+        issues.push(BundleIssue::InvalidStep { step: meta.step });
+    }
+    if let Some(Err(reason)) = meta.encoding_spec().map(|spec| spec.check_supported()) {
+        issues.push(BundleIssue::UnsupportedEncoding { reason });
+    }
+    issues
+}
+
+/// Name to import `name` under given the store's contents, or `None` to skip it
+pub(crate) fn resolve_import_name(name: &str, store: &PhiMemoryStore, policy: ImportPolicy) -> PhiResult<Option<String>> {
+    if !store.contains(name)? {
+        return Ok(Some(name.to_string()));
+    }
+    match policy {
+        ImportPolicy::Reject => Err(PhiError::Validation(format!("'{}' already exists in the store", name))),
+        ImportPolicy::Skip => Ok(None),
+        ImportPolicy::Overwrite => Ok(Some(name.to_string())),
+        ImportPolicy::Rename => {
+            let mut suffix = 1;
+            while store.contains(&format!("{}-{}", name, suffix))? {
+                suffix += 1;
+            }
+            Ok(Some(format!("{}-{}", name, suffix)))
+        }
+    }
+}
+
+/// Signature of the bundle at `path`: the embedded one if there is one, otherwise `<path>.sig`
+#[cfg(feature = "signing")]
+pub(crate) fn bundle_signature(path: &Path, embedded: Option<&BundleSignature>) -> PhiResult<BundleSignature> {
+    match embedded {
+        Some(signature) => Ok(signature.clone()),
+        None if signature_path(path).exists() => BundleSignature::load(signature_path(path)),
+        None => Err(PhiError::Validation("bundle is not signed".to_string())),
+    }
+}
+
+/// Fail if the embedded content hash does not match the bundle as stored
+fn check_hash(bundle: &PhiBundle) -> PhiResult<()> {
+    if let Some(recorded) = &bundle.content_hash {
+        let actual = bundle.compute_hash()?;
+        if *recorded != actual {
+            return Err(PhiError::Validation(format!(
+                "content hash {} does not match bundle contents ({})",
+                recorded, actual
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Verify the embedded content hash, then bring the bundle up to the current schema
+/// Check the content hash and a trusted signature of a bundle read from `path`, as stored;
+/// returns the signer's label
+///
+/// The embedded signature is used if there is one, otherwise `<path>.sig`.
+#[cfg(feature = "signing")]
+fn verify_signature(path: &Path, bundle: &PhiBundle, trusted: &TrustedKeys) -> PhiResult<String> {
+    check_hash(bundle)?;
+    let signature = bundle_signature(path, bundle.signature.as_ref())?;
+    trusted.verify(&bundle.signing_message()?, &signature)
+}
+
+fn upgraded(mut bundle: PhiBundle) -> PhiResult<PhiBundle> {
+    check_hash(&bundle)?;
+    upgrade_bundle(&mut bundle)?;
+    Ok(bundle)
+}
+
 impl PhiBundle {
-    /// Construct bundle from memory store
-    pub fn from_store(name: &str, store: &PhiMemoryStore) -> std::io::Result<Self> {
-        let data = store.load(name)?;
-        let metadata = PhiMetadata::load(name, store.base_path())?;
-        Ok(Self {
+    pub fn new(name: &str, metadata: PhiMetadata, data: Vec<f64>) -> Self {
+        Self {
+            schema_version: BUNDLE_SCHEMA_VERSION,
             name: name.to_string(),
             metadata,
+            content_hash: None,
+            signature: None,
             data,
+        }
+    }
+
+    /// FNV-1a over the name, the metadata as JSON and the data as little-endian f64
+    pub fn compute_hash(&self) -> PhiResult<String> {
+        let mut hasher = content_hasher(&self.name, &self.metadata)?;
+        for v in &self.data {
+            hasher.update(&v.to_le_bytes());
+        }
+        Ok(format!("{:016x}", hasher.finish()))
+    }
+
+    /// Name, metadata as JSON and data as little-endian f64, separated by NUL bytes
+    fn content_bytes(&self) -> PhiResult<Vec<u8>> {
+        let mut bytes = content_prefix(&self.name, &self.metadata)?;
+        bytes.extend(self.data.iter().flat_map(|v| v.to_le_bytes()));
+        Ok(bytes)
+    }
+
+    /// Bytes covered by a bundle signature
+    pub fn signing_message(&self) -> PhiResult<Vec<u8>> {
+        let mut message = SIGNING_DOMAIN.to_vec();
+        message.extend(self.content_bytes()?);
+        Ok(message)
+    }
+
+    fn export_view(&self, with_data: bool) -> PhiResult<ExportView<'_>> {
+        Ok(ExportView {
+            schema_version: self.schema_version,
+            name: &self.name,
+            metadata: &self.metadata,
+            content_hash: Some(self.compute_hash()?),
+            signature: self.signature.as_ref(),
+            data: with_data.then_some(&self.data[..]),
         })
     }
 
+    /// Check the bundle is consistent and safe to write into a store
+    pub fn validate(&self) -> BundleReport {
+        let meta = &self.metadata;
+        let mut issues = header_issues(&self.name, meta);
+        if meta.length != self.data.len() {
+            issues.push(BundleIssue::LengthMismatch { recorded: meta.length, actual: self.data.len() });
+        }
+        let mut non_finite = self.data.iter().enumerate().filter(|(_, v)| !v.is_finite());
+        if let Some((first, _)) = non_finite.next() {
+            issues.push(BundleIssue::NonFinite { first, count: 1 + non_finite.count() });
+        }
+        if let Some(recorded) = meta.checksum {
+            let actual = phi_checksum(&self.data);
+            if recorded != actual {
+                issues.push(BundleIssue::ChecksumMismatch { recorded, actual });
+            }
+        }
+        BundleReport { issues }
+    }
+
+    /// Construct bundle from memory store
+    pub fn from_store(name: &str, store: &PhiMemoryStore) -> PhiResult<Self> {
+        let data = store.load(name)?;
+        let metadata = store.read_meta(name)?;
+        Ok(Self::new(name, metadata, data))
+    }
+
     /// Save bundle to JSON file
-    pub fn save_json<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
-        let json = serde_json::to_string_pretty(self).unwrap();
-        fs::write(path, json)
+    pub fn save_json<P: AsRef<Path>>(&self, path: P) -> PhiResult<()> {
+        let json = serde_json::to_string_pretty(&self.export_view(true)?)?;
+        fs::write(path, json)?;
+        Ok(())
+    }
+
+    /// Load bundle from JSON file, with the extra checks `options` asks for
+    #[cfg_attr(not(feature = "signing"), allow(unused_variables))]
+    pub fn load_json<P: AsRef<Path>>(path: P, options: &LoadOptions) -> PhiResult<Self> {
+        let path = path.as_ref();
+        let bytes = fs::read(path)?;
+        if is_sealed(&bytes) {
+            return Err(PhiError::format("bundle is encrypted, use load_encrypted"));
+        }
+        let bundle: Self = serde_json::from_slice(&bytes)?;
+        #[cfg(feature = "signing")]
+        if let Some(trusted) = &options.trusted {
+            verify_signature(path, &bundle, trusted)?;
+        }
+        upgraded(bundle)
+    }
+
+    /// Encode as `PHIB`, version, u32 header length, JSON header, u64 payload length, payload
+    ///
+    /// The payload is plain little-endian f64, never compressed.
+    pub fn to_bytes(&self) -> PhiResult<Vec<u8>> {
+        let payload = raw_bytes(&self.data);
+        let mut out = binary_prefix(&self.export_view(false)?, payload.len() as u64)?;
+        out.extend_from_slice(&payload);
+        Ok(out)
+    }
+
+    /// Decode a bundle produced by `to_bytes`
+    pub fn from_bytes(bytes: &[u8]) -> PhiResult<Self> {
+        upgraded(Self::parse_binary(bytes)?)
+    }
+
+    /// Decode a binary bundle as stored, without checks or upgrades
+    fn parse_binary(bytes: &[u8]) -> PhiResult<Self> {
+        let mut payload = bytes;
+        let (header, payload_len) = read_binary_header(&mut payload)?;
+        if payload.len() as u64 != payload_len {
+            return Err(PhiError::format("binary bundle payload has wrong length"));
+        }
+
+        Ok(Self {
+            schema_version: header.schema_version,
+            name: header.name,
+            metadata: header.metadata,
+            content_hash: header.content_hash,
+            signature: header.signature,
+            data: raw_values(payload),
+        })
+    }
+
+    /// Save bundle in the binary format
+    pub fn save_binary<P: AsRef<Path>>(&self, path: P) -> PhiResult<()> {
+        fs::write(path, self.to_bytes()?)?;
+        Ok(())
+    }
+
+    /// Load a JSON or binary bundle, detecting the format from its first bytes
+    pub fn load<P: AsRef<Path>>(path: P) -> PhiResult<Self> {
+        let bytes = fs::read(path)?;
+        if bytes.starts_with(BINARY_MAGIC) {
+            Self::from_bytes(&bytes)
+        } else if is_sealed(&bytes) {
+            Err(PhiError::format("bundle is encrypted, use load_encrypted"))
+        } else {
+            upgraded(serde_json::from_slice(&bytes)?)
+        }
+    }
+
+    /// Embed a signature by `signer`; it is written by every `save_*`
+    #[cfg(feature = "signing")]
+    pub fn sign(&mut self, signer: &PhiSigner) -> PhiResult<()> {
+        self.signature = Some(signer.sign(&self.signing_message()?));
+        Ok(())
     }
 
-    /// Load bundle from JSON file
-    pub fn load_json<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
-        let json = fs::read_to_string(path)?;
-        let bundle: Self = serde_json::from_str(&json).unwrap();
-        Ok(bundle)
+    /// Signature by `signer` to ship next to the bundle file (see `signature_path`)
+    #[cfg(feature = "signing")]
+    pub fn sign_detached(&self, signer: &PhiSigner) -> PhiResult<BundleSignature> {
+        Ok(signer.sign(&self.signing_message()?))
     }
 
-    /// Restore to memory store
-    pub fn save_to_store(&self, store: &PhiMemoryStore) -> std::io::Result<()> {
-        store.save(&self.name, &self.data)?;
-        self.metadata.save(&self.name, store.base_path())
+    /// `load` that only accepts a bundle signed by a trusted key; returns the signer's label
+    ///
+    /// Checks like `load_json` with `LoadOptions::verified_by`, for either format.
+    #[cfg(feature = "signing")]
+    pub fn load_verified<P: AsRef<Path>>(path: P, trusted: &TrustedKeys) -> PhiResult<(Self, String)> {
+        let path = path.as_ref();
+        let bytes = fs::read(path)?;
+        let bundle = if bytes.starts_with(BINARY_MAGIC) {
+            Self::parse_binary(&bytes)?
+        } else {
+            serde_json::from_slice(&bytes)?
+        };
+        let signer = verify_signature(path, &bundle, trusted)?;
+        Ok((upgraded(bundle)?, signer))
+    }
+
+    /// Save bundle as encrypted, authenticated JSON
+    #[cfg(feature = "encryption")]
+    pub fn save_encrypted<P: AsRef<Path>>(&self, path: P, key: &PhiKey) -> PhiResult<()> {
+        let json = serde_json::to_vec(&self.export_view(true)?)?;
+        fs::write(path, seal(key, &json, BUNDLE_AAD)?)?;
+        Ok(())
+    }
+
+    /// Load bundle written by `save_encrypted`, failing if it was tampered with
+    #[cfg(feature = "encryption")]
+    pub fn load_encrypted<P: AsRef<Path>>(path: P, key: &PhiKey) -> PhiResult<Self> {
+        let json = open(key, &fs::read(path)?, BUNDLE_AAD)?;
+        upgraded(serde_json::from_slice(&json)?)
+    }
+
+    /// Restore to memory store, replacing an entry of the same name
+    pub fn save_to_store(&self, store: &PhiMemoryStore) -> PhiResult<()> {
+        self.import_into(store, ImportPolicy::Overwrite).map(|_| ())
+    }
+
+    /// Validate the bundle and write it into a store, resolving name collisions by `policy`
+    pub fn import_into(&self, store: &PhiMemoryStore, policy: ImportPolicy) -> PhiResult<ImportOutcome> {
+        self.validate().into_result()?;
+        let Some(name) = resolve_import_name(&self.name, store, policy)? else {
+            return Ok(ImportOutcome::Skipped);
+        };
+        store.save(&name, &self.data)?;
+        store.save_meta(&name, &self.metadata)?;
+        Ok(ImportOutcome::Imported(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_binary_bundle_roundtrip_and_detection() {
+        let mut metadata = PhiMetadata::new(10, 0.01, 3);
+        metadata.unit = Some("mV".to_string());
+        let bundle = PhiBundle::new("sig", metadata, vec![4.2, -1.5, f64::MIN_POSITIVE]);
+
+        let bytes = bundle.to_bytes().unwrap();
+        let json = serde_json::to_vec_pretty(&bundle).unwrap();
+        assert!(bytes.len() < json.len());
+        let decoded = PhiBundle::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.data, bundle.data);
+        assert_eq!(decoded.metadata, bundle.metadata);
+
+        bundle.save_binary("test_bundle_binary.phib").unwrap();
+        bundle.save_json("test_bundle_binary.json").unwrap();
+        for path in ["test_bundle_binary.phib", "test_bundle_binary.json"] {
+            let loaded = PhiBundle::load(path).unwrap();
+            assert_eq!(loaded.name, "sig");
+            assert_eq!(loaded.data, bundle.data);
+            fs::remove_file(path).unwrap();
+        }
+
+        // Truncated or corrupted frames fail instead of panicking
+        assert!(PhiBundle::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(PhiBundle::from_bytes(&bytes[..7]).is_err());
+        let mut bad_version = bytes.clone();
+        bad_version[4] = 99;
+        assert!(PhiBundle::from_bytes(&bad_version).is_err());
+
+        // A header length past the end of the input is truncated rather than allocated
+        let mut huge_header = bytes[..9].to_vec();
+        huge_header[5..9].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(PhiBundle::from_bytes(&huge_header), Err(PhiError::Format(_))));
+    }
+
+    #[test]
+    fn test_malformed_bundle_is_an_error() {
+        let path = "test_bundle_malformed.json";
+        fs::write(path, "{\"name\": \"sig\", \"data\": [1.0,").unwrap();
+        let err = PhiBundle::load_json(path, &LoadOptions::default()).unwrap_err();
+        assert!(matches!(err, PhiError::Parse { what: "JSON", .. }));
+        assert!(std::error::Error::source(&err).is_some());
+        assert!(matches!(PhiBundle::load("test_bundle_missing.json"), Err(PhiError::Io(_))));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_validate_reports_every_issue() {
+        let mut metadata = PhiMetadata::new(0, -1.0, 4);
+        metadata.checksum = Some(1);
+        let bundle = PhiBundle::new("../escape", metadata, vec![1.0, f64::NAN, f64::INFINITY]);
+        let report = bundle.validate();
+        assert_eq!(report.issues.len(), 6);
+        assert!(report.issues.contains(&BundleIssue::NonFinite { first: 1, count: 2 }));
+        assert!(report.issues.contains(&BundleIssue::LengthMismatch { recorded: 4, actual: 3 }));
+        assert!(matches!(report.into_result(), Err(PhiError::Validation(_))));
+
+        let mut metadata = PhiMetadata::new(10, 0.01, 2);
+        metadata.checksum = Some(phi_checksum(&[1.0, 2.0]));
+        assert!(PhiBundle::new("ok", metadata, vec![1.0, 2.0]).validate().is_valid());
+    }
+
+    #[test]
+    fn test_content_hash_detects_tampering() {
+        let bundle = PhiBundle::new("sig", PhiMetadata::new(10, 0.01, 2), vec![1.0, 2.0]);
+        let path = "test_bundle_hash.json";
+        bundle.save_json(path).unwrap();
+        let loaded = PhiBundle::load(path).unwrap();
+        assert_eq!(loaded.content_hash, Some(bundle.compute_hash().unwrap()));
+
+        let tampered = fs::read_to_string(path).unwrap().replace("2.0", "3.0");
+        fs::write(path, tampered).unwrap();
+        assert!(matches!(PhiBundle::load(path), Err(PhiError::Validation(_))));
+
+        let mut bytes = bundle.to_bytes().unwrap();
+        *bytes.last_mut().unwrap() ^= 1;
+        assert!(matches!(PhiBundle::from_bytes(&bytes), Err(PhiError::Validation(_))));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_import_policies() {
+        let store = PhiMemoryStore::new(".phi_bundle_import_store");
+        let first = PhiBundle::new("sig", PhiMetadata::new(10, 0.01, 1), vec![1.0]);
+        let second = PhiBundle::new("sig", PhiMetadata::new(10, 0.01, 1), vec![2.0]);
+        assert_eq!(first.import_into(&store, ImportPolicy::Reject).unwrap(), ImportOutcome::Imported("sig".into()));
+
+        assert!(second.import_into(&store, ImportPolicy::Reject).is_err());
+        assert_eq!(second.import_into(&store, ImportPolicy::Skip).unwrap(), ImportOutcome::Skipped);
+        assert_eq!(store.load("sig").unwrap(), vec![1.0]);
+
+        let outcome = second.import_into(&store, ImportPolicy::Rename).unwrap();
+        assert_eq!(outcome, ImportOutcome::Imported("sig-1".into()));
+        assert_eq!(store.load("sig-1").unwrap(), vec![2.0]);
+
+        second.import_into(&store, ImportPolicy::Overwrite).unwrap();
+        assert_eq!(store.load("sig").unwrap(), vec![2.0]);
+
+        let invalid = PhiBundle::new("bad", PhiMetadata::new(10, 0.01, 5), vec![1.0]);
+        assert!(invalid.save_to_store(&store).is_err());
+        assert!(!store.contains("bad").unwrap());
+
+        fs::remove_dir_all(".phi_bundle_import_store").unwrap();
     }
 }
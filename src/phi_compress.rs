@@ -0,0 +1,480 @@
+//! phi_compress: at-rest compression for stored φ-data
+//! Byte-shuffles f64 payloads, then deflates them (LZ4 and zstd behind features)
+//! Store payloads open with a `PHIZ` header naming their codec, uncompressed ones included;
+//! compressed ones are split into frames that can be read and written one at a time
+
+use crate::phi_error::{PhiError, PhiResult};
+use serde::{Deserialize, Serialize};
+use std::io::{Chain, Cursor, ErrorKind, Read, Write};
+
+/// Magic bytes opening every payload written by `encode_payload`
+pub const COMPRESSED_MAGIC: &[u8; 4] = b"PHIZ";
+/// Length of the payload header: magic, codec id, flags, u64 original length
+pub const HEADER_LEN: usize = 14;
+/// Values per frame of a compressed payload
+pub const FRAME_LEN: usize = 64 * 1024;
+const FLAG_SHUFFLED: u8 = 1;
+const FLAG_FRAMED: u8 = 2;
+
+/// Compression codec for `.bin` payloads
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    /// Little-endian f64 stored as is after the header
+    #[default]
+    None,
+    /// In-crate byte shuffle + deflate
+    Deflate,
+    /// LZ4 block compression (feature `lz4`)
+    Lz4,
+    /// Zstandard (feature `zstd`)
+    Zstd,
+}
+
+impl Compression {
+    fn id(self) -> u8 {
+        match self {
+            Compression::None => 0,
+            Compression::Deflate => 1,
+            Compression::Lz4 => 2,
+            Compression::Zstd => 3,
+        }
+    }
+
+    fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(Compression::None),
+            1 => Some(Compression::Deflate),
+            2 => Some(Compression::Lz4),
+            3 => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Compression::None => "none",
+            Compression::Deflate => "deflate",
+            Compression::Lz4 => "lz4",
+            Compression::Zstd => "zstd",
+        }
+    }
+}
+
+fn unsupported(codec: Compression) -> PhiError {
+    PhiError::Unsupported(format!("compression '{}' (enable its feature)", codec.name()))
+}
+
+fn corrupt<E: std::fmt::Display>(codec: Compression, err: E) -> PhiError {
+    PhiError::format(format!("corrupt {} payload: {}", codec.name(), err))
+}
+
+/// Group byte `k` of every value together so similar bytes sit next to each other
+fn shuffle(bytes: &[u8]) -> Vec<u8> {
+    let count = bytes.len() / 8;
+    let mut out = vec![0u8; bytes.len()];
+    for (i, value) in bytes.chunks_exact(8).enumerate() {
+        for (k, &b) in value.iter().enumerate() {
+            out[k * count + i] = b;
+        }
+    }
+    out
+}
+
+fn unshuffle(bytes: &[u8]) -> Vec<u8> {
+    let count = bytes.len() / 8;
+    let mut out = vec![0u8; bytes.len()];
+    for (i, value) in out.chunks_exact_mut(8).enumerate() {
+        for (k, b) in value.iter_mut().enumerate() {
+            *b = bytes[k * count + i];
+        }
+    }
+    out
+}
+
+fn compress_bytes(codec: Compression, bytes: &[u8]) -> PhiResult<Vec<u8>> {
+    match codec {
+        Compression::None => Ok(bytes.to_vec()),
+        Compression::Deflate => Ok(miniz_oxide::deflate::compress_to_vec(bytes, 6)),
+        #[cfg(feature = "lz4")]
+        Compression::Lz4 => Ok(lz4_flex::block::compress(bytes)),
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => Ok(zstd::bulk::compress(bytes, 3)?),
+        #[allow(unreachable_patterns)]
+        other => Err(unsupported(other)),
+    }
+}
+
+fn decompress_bytes(codec: Compression, bytes: &[u8], original_len: usize) -> PhiResult<Vec<u8>> {
+    match codec {
+        Compression::None => Ok(bytes.to_vec()),
+        Compression::Deflate => miniz_oxide::inflate::decompress_to_vec_with_limit(bytes, original_len)
+            .map_err(|e| corrupt(codec, format!("{:?}", e.status))),
+        #[cfg(feature = "lz4")]
+        Compression::Lz4 => lz4_flex::block::decompress(bytes, original_len)
+            .map_err(|e| corrupt(codec, e)),
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => zstd::bulk::decompress(bytes, original_len).map_err(|e| corrupt(codec, e)),
+        #[allow(unreachable_patterns)]
+        other => Err(unsupported(other)),
+    }
+}
+
+/// Header at the start of a payload
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct PayloadHeader {
+    pub(crate) codec: Compression,
+    flags: u8,
+    /// Length in bytes of the values, before compression
+    pub(crate) original_len: usize,
+}
+
+impl PayloadHeader {
+    /// Header for a payload of `original_len` bytes written with `codec` by this version
+    pub(crate) fn new(codec: Compression, original_len: usize) -> Self {
+        let flags = if codec == Compression::None { 0 } else { FLAG_SHUFFLED | FLAG_FRAMED };
+        Self { codec, flags, original_len }
+    }
+
+    /// Parse a payload header
+    ///
+    /// `None` means the bytes are a headerless payload from before every payload had one.
+    pub(crate) fn parse(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < HEADER_LEN || &bytes[..4] != COMPRESSED_MAGIC {
+            return None;
+        }
+        let codec = Compression::from_id(bytes[4])?;
+        let mut len = [0u8; 8];
+        len.copy_from_slice(&bytes[6..HEADER_LEN]);
+        let original_len = u64::from_le_bytes(len) as usize;
+        if !original_len.is_multiple_of(8) {
+            return None;
+        }
+        Some(Self { codec, flags: bytes[5], original_len })
+    }
+
+    /// Fail unless a body of `body_len` bytes can hold `original_len` bytes of values: all of
+    /// them when uncompressed, or a u32 length for each frame
+    ///
+    /// Compressed payloads are always framed, so a frame never decompresses to more than
+    /// `FRAME_LEN` values whatever the header claims.
+    fn check_fits(self, body_len: u64) -> PhiResult<()> {
+        let needed = match self.codec {
+            Compression::None => self.original_len as u64,
+            _ if self.framed() => self.original_len.div_ceil(FRAME_LEN * 8) as u64 * 4,
+            _ => return Err(corrupt(self.codec, "not framed")),
+        };
+        if needed > body_len {
+            return Err(corrupt(self.codec, "length exceeds payload"));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn to_bytes(self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(HEADER_LEN);
+        out.extend_from_slice(COMPRESSED_MAGIC);
+        out.push(self.codec.id());
+        out.push(self.flags);
+        out.extend_from_slice(&(self.original_len as u64).to_le_bytes());
+        out
+    }
+
+    /// Compressed in frames of `FRAME_LEN` values rather than as one block
+    fn framed(self) -> bool {
+        self.flags & FLAG_FRAMED != 0
+    }
+}
+
+/// Little-endian bytes of φ-data, without a header
+pub(crate) fn raw_bytes(data: &[f64]) -> Vec<u8> {
+    data.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+/// φ-data from little-endian bytes, without a header
+pub(crate) fn raw_values(bytes: &[u8]) -> Vec<f64> {
+    bytes
+        .chunks_exact(8)
+        .map(|c| f64::from_le_bytes(c.try_into().unwrap()))
+        .collect()
+}
+
+/// Writes a payload whose values arrive in pieces, compressing one frame at a time
+///
+/// A compressed frame is a u32 length followed by the shuffled, compressed values.
+pub(crate) struct PayloadWriter<W: Write> {
+    out: W,
+    codec: Compression,
+    pending: Vec<u8>,
+}
+
+impl<W: Write> PayloadWriter<W> {
+    /// Write the header of a payload holding `len` values
+    pub(crate) fn new(mut out: W, codec: Compression, len: usize) -> PhiResult<Self> {
+        out.write_all(&PayloadHeader::new(codec, len * 8).to_bytes())?;
+        Ok(Self { out, codec, pending: Vec::new() })
+    }
+
+    /// Append little-endian values
+    pub(crate) fn write(&mut self, bytes: &[u8]) -> PhiResult<()> {
+        if self.codec == Compression::None {
+            self.out.write_all(bytes)?;
+            return Ok(());
+        }
+        self.pending.extend_from_slice(bytes);
+        while self.pending.len() >= FRAME_LEN * 8 {
+            let rest = self.pending.split_off(FRAME_LEN * 8);
+            self.write_frame()?;
+            self.pending = rest;
+        }
+        Ok(())
+    }
+
+    fn write_frame(&mut self) -> PhiResult<()> {
+        let body = compress_bytes(self.codec, &shuffle(&self.pending))?;
+        self.out.write_all(&(body.len() as u32).to_le_bytes())?;
+        self.out.write_all(&body)?;
+        self.pending.clear();
+        Ok(())
+    }
+
+    /// Write the last frame and hand back the output
+    pub(crate) fn finish(mut self) -> PhiResult<W> {
+        if !self.pending.is_empty() {
+            self.write_frame()?;
+        }
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+/// Reads the values of a payload in pieces of at most `FRAME_LEN`, decompressing one frame at a time
+pub(crate) struct PayloadReader<R: Read> {
+    input: Chain<Cursor<Vec<u8>>, R>,
+    header: Option<PayloadHeader>,
+    remaining: usize,
+    len: usize,
+}
+
+impl<R: Read> PayloadReader<R> {
+    /// Read the header of a payload `total_len` bytes long
+    pub(crate) fn new(mut input: R, total_len: u64) -> PhiResult<Self> {
+        let mut head = Vec::with_capacity(HEADER_LEN);
+        (&mut input).take(HEADER_LEN as u64).read_to_end(&mut head)?;
+        // Raw values can start like a header by chance; an uncompressed header must also
+        // account for exactly the bytes that follow it
+        let body_len = total_len.saturating_sub(HEADER_LEN as u64);
+        let header = PayloadHeader::parse(&head)
+            .filter(|h| h.codec != Compression::None || h.original_len as u64 == body_len);
+        let original_len = match header {
+            Some(header) => {
+                head.clear();
+                header.check_fits(body_len)?;
+                header.original_len
+            }
+            None => (total_len / 8 * 8) as usize,
+        };
+        Ok(Self { input: Cursor::new(head).chain(input), header, remaining: original_len, len: original_len / 8 })
+    }
+
+    /// Codec the payload was written with
+    pub(crate) fn codec(&self) -> Compression {
+        self.header.map_or(Compression::None, |header| header.codec)
+    }
+
+    /// Number of values in the payload
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Next little-endian values, `None` once all were read
+    pub(crate) fn next_bytes(&mut self) -> PhiResult<Option<Vec<u8>>> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        let codec = self.codec();
+        let bytes = match self.header {
+            Some(_) if codec != Compression::None => {
+                let mut len = [0u8; 4];
+                self.read_exact(&mut len)?;
+                // Read the body as it arrives rather than trusting its length up front
+                let body_len = u32::from_le_bytes(len) as u64;
+                let mut body = Vec::new();
+                (&mut self.input).take(body_len).read_to_end(&mut body)?;
+                if (body.len() as u64) < body_len {
+                    return Err(corrupt(codec, "truncated"));
+                }
+                let expected = self.remaining.min(FRAME_LEN * 8);
+                let frame = decompress_bytes(codec, &body, expected)?;
+                if frame.len() != expected {
+                    return Err(corrupt(codec, "wrong frame length"));
+                }
+                unshuffle(&frame)
+            }
+            _ => {
+                let mut bytes = vec![0u8; self.remaining.min(FRAME_LEN * 8)];
+                self.read_exact(&mut bytes)?;
+                bytes
+            }
+        };
+        self.remaining -= bytes.len();
+        Ok(Some(bytes))
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> PhiResult<()> {
+        self.input.read_exact(buf).map_err(|err| match err.kind() {
+            ErrorKind::UnexpectedEof => corrupt(self.codec(), "truncated"),
+            _ => err.into(),
+        })
+    }
+}
+
+/// Encode φ-data to its on-disk byte form with the given codec
+pub fn encode_payload(data: &[f64], codec: Compression) -> PhiResult<Vec<u8>> {
+    let mut writer = PayloadWriter::new(Vec::new(), codec, data.len())?;
+    writer.write(&raw_bytes(data))?;
+    writer.finish()
+}
+
+/// Decode on-disk bytes back to φ-data, reading the codec from the header
+///
+/// Bytes without a header are read as plain little-endian f64, as written by `save_quantized`
+/// and by earlier versions of the store.
+pub fn decode_payload(bytes: &[u8]) -> PhiResult<Vec<f64>> {
+    let mut reader = PayloadReader::new(bytes, bytes.len() as u64)?;
+    let mut data = Vec::with_capacity(reader.len().min(bytes.len() / 8));
+    while let Some(chunk) = reader.next_bytes()? {
+        data.extend(raw_values(&chunk));
+    }
+    Ok(data)
+}
+
+/// Codec a payload was written with
+pub fn detect_compression(bytes: &[u8]) -> Compression {
+    PayloadHeader::parse(bytes).map_or(Compression::None, |header| header.codec)
+}
+
+/// Number of φ-values in a payload without decompressing it
+pub fn payload_len(bytes: &[u8]) -> usize {
+    match PayloadHeader::parse(bytes) {
+        Some(header) => header.original_len / 8,
+        None => bytes.len() / 8,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::phi_fs::PhiMemoryStore;
+    use crate::signal::generate_sine_wave;
+    use std::fs;
+
+    #[test]
+    fn test_deflate_roundtrip_and_detection() {
+        let data: Vec<f64> = generate_sine_wave(512, 3.0, 0.0)
+            .iter()
+            .map(|x| (x / 0.01).round() * 0.01)
+            .collect();
+        let packed = encode_payload(&data, Compression::Deflate).unwrap();
+        assert!(packed.len() < data.len() * 8 / 2);
+        assert_eq!(detect_compression(&packed), Compression::Deflate);
+        assert_eq!(decode_payload(&packed).unwrap(), data);
+
+        let plain = encode_payload(&data, Compression::None).unwrap();
+        assert_eq!(&plain[..4], COMPRESSED_MAGIC);
+        assert_eq!(detect_compression(&plain), Compression::None);
+        assert_eq!(decode_payload(&plain).unwrap(), data);
+
+        // Plain values that happen to start like a header are still read by their header
+        let lookalike = [f64::from_le_bytes(*b"PHIZ\x01\x01\x08\x00"), 0.0, 1.0];
+        let plain = encode_payload(&lookalike, Compression::None).unwrap();
+        assert_eq!(decode_payload(&plain).unwrap(), lookalike);
+
+        // Headerless payloads from earlier versions still load
+        let legacy: Vec<u8> = data.iter().flat_map(|v| v.to_le_bytes()).collect();
+        assert_eq!(decode_payload(&legacy).unwrap(), data);
+
+        // Compressed payloads are always framed; one deflated as a single block is rejected
+        let mut unframed = COMPRESSED_MAGIC.to_vec();
+        unframed.extend_from_slice(&[1, FLAG_SHUFFLED]);
+        unframed.extend_from_slice(&(legacy.len() as u64).to_le_bytes());
+        unframed.extend(miniz_oxide::deflate::compress_to_vec(&shuffle(&legacy), 6));
+        assert!(decode_payload(&unframed).is_err());
+    }
+
+    #[test]
+    fn test_frames_stream_in_bounded_pieces() {
+        let data: Vec<f64> = (0..2 * FRAME_LEN + 3).map(|i| (i % 7) as f64).collect();
+        let packed = encode_payload(&data, Compression::Deflate).unwrap();
+        let mut reader = PayloadReader::new(&packed[..], packed.len() as u64).unwrap();
+        let mut sizes = Vec::new();
+        while let Some(bytes) = reader.next_bytes().unwrap() {
+            sizes.push(bytes.len() / 8);
+        }
+        assert_eq!(sizes, vec![FRAME_LEN, FRAME_LEN, 3]);
+        assert_eq!(decode_payload(&packed).unwrap(), data);
+        assert!(decode_payload(&packed[..packed.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_corrupt_header_length_is_an_error() {
+        let mut bytes = PayloadHeader::new(Compression::Deflate, 0).to_bytes();
+        bytes[6..HEADER_LEN].copy_from_slice(&(u64::MAX - 7).to_le_bytes());
+        assert!(decode_payload(&bytes).is_err());
+
+        // An uncompressed header not matching the bytes after it is raw data, not a claim to trust
+        let mut bytes = PayloadHeader::new(Compression::None, 0).to_bytes();
+        bytes[6..HEADER_LEN].copy_from_slice(&(u64::MAX - 7).to_le_bytes());
+        assert_eq!(decode_payload(&bytes).unwrap().len(), 1);
+
+        // Unframed headers are rejected before anything is decompressed, whatever length they claim
+        for codec in [Compression::Deflate, Compression::Lz4, Compression::Zstd] {
+            let mut bytes = COMPRESSED_MAGIC.to_vec();
+            bytes.extend_from_slice(&[codec.id(), FLAG_SHUFFLED]);
+            bytes.extend_from_slice(&(u64::MAX - 7).to_le_bytes());
+            bytes.extend_from_slice(&[0; 16]);
+            assert!(decode_payload(&bytes).is_err());
+        }
+
+        // A frame claiming more bytes than the payload has is truncated, not allocated
+        let mut packed = encode_payload(&[1.0; 16], Compression::Deflate).unwrap();
+        packed[HEADER_LEN..HEADER_LEN + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(decode_payload(&packed).is_err());
+    }
+
+    #[test]
+    fn test_store_compression_per_store_and_entry() {
+        let store = PhiMemoryStore::new(".phi_compress_store").with_compression(Compression::Deflate);
+        let data = vec![0.5; 256];
+        store.save("packed", &data).unwrap();
+        store.save_with_compression("plain", &data, Compression::None).unwrap();
+
+        let manifest = store.manifest().unwrap();
+        assert_eq!(manifest.get("packed").unwrap().compression, Compression::Deflate);
+        assert_eq!(manifest.get("plain").unwrap().compression, Compression::None);
+        assert!(fs::metadata(".phi_compress_store/packed.bin").unwrap().len() < 256 * 8);
+
+        // Loads detect compression on their own, whatever the store default is
+        let reader = PhiMemoryStore::new(".phi_compress_store");
+        assert_eq!(reader.load("packed").unwrap(), data);
+        assert_eq!(reader.load("plain").unwrap(), data);
+        assert_eq!(payload_len(&fs::read(".phi_compress_store/packed.bin").unwrap()), 256);
+
+        fs::remove_dir_all(".phi_compress_store").unwrap();
+    }
+
+    #[cfg(feature = "lz4")]
+    #[test]
+    fn test_lz4_roundtrip() {
+        let data = vec![1.25; 100];
+        let packed = encode_payload(&data, Compression::Lz4).unwrap();
+        assert_eq!(decode_payload(&packed).unwrap(), data);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd_roundtrip() {
+        let data = vec![1.25; 100];
+        let packed = encode_payload(&data, Compression::Zstd).unwrap();
+        assert_eq!(decode_payload(&packed).unwrap(), data);
+    }
+}
@@ -0,0 +1,178 @@
+//! phi_crypto: authenticated encryption of stored φ-memories and bundles
+//! ChaCha20-Poly1305 with a caller-supplied key and a fresh nonce per write (feature `encryption`)
+
+/// Magic bytes opening every sealed payload
+pub const ENCRYPTED_MAGIC: &[u8; 4] = b"PHIE";
+const FORMAT_VERSION: u8 = 1;
+#[cfg(feature = "encryption")]
+const NONCE_LEN: usize = 12;
+#[cfg(feature = "encryption")]
+const HEADER_LEN: usize = 5 + NONCE_LEN;
+
+/// Associated data binding an encrypted bundle file to its purpose
+pub const BUNDLE_AAD: &[u8] = b"phi-bundle";
+
+/// True if the bytes start like the output of `seal`
+///
+/// Only meaningful for files whose plain form cannot start with `PHIE`, such as bundles;
+/// stores know whether they are encrypted instead of looking at their payloads.
+pub fn is_sealed(bytes: &[u8]) -> bool {
+    bytes.len() > 5 && &bytes[..4] == ENCRYPTED_MAGIC && bytes[4] == FORMAT_VERSION
+}
+
+#[cfg(feature = "encryption")]
+pub use self::aead::*;
+
+#[cfg(feature = "encryption")]
+mod aead {
+    use super::*;
+    use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+    use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+    use crate::phi_error::{PhiError, PhiResult};
+    use rand::RngCore;
+
+    /// 256-bit symmetric key supplied by the caller
+    #[derive(Clone)]
+    pub struct PhiKey([u8; 32]);
+
+    impl PhiKey {
+        pub fn from_bytes(bytes: [u8; 32]) -> Self {
+            Self(bytes)
+        }
+
+        /// Fresh random key
+        pub fn generate() -> Self {
+            let mut bytes = [0u8; 32];
+            rand::thread_rng().fill_bytes(&mut bytes);
+            Self(bytes)
+        }
+
+        pub fn as_bytes(&self) -> &[u8; 32] {
+            &self.0
+        }
+    }
+
+    impl std::fmt::Debug for PhiKey {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str("PhiKey(..)")
+        }
+    }
+
+    /// Encrypt and authenticate `plaintext`; `aad` must match on `open`
+    pub fn seal(key: &PhiKey, plaintext: &[u8], aad: &[u8]) -> PhiResult<Vec<u8>> {
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(&key.0));
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let ciphertext = cipher
+            .encrypt(Nonce::from_slice(&nonce), Payload { msg: plaintext, aad })
+            .map_err(|_| PhiError::Validation("data is too large to encrypt".to_string()))?;
+
+        let mut out = Vec::with_capacity(HEADER_LEN + ciphertext.len());
+        out.extend_from_slice(ENCRYPTED_MAGIC);
+        out.push(FORMAT_VERSION);
+        out.extend_from_slice(&nonce);
+        out.extend_from_slice(&ciphertext);
+        Ok(out)
+    }
+
+    /// Decrypt sealed bytes, failing if they were tampered with or the key/aad is wrong
+    pub fn open(key: &PhiKey, sealed: &[u8], aad: &[u8]) -> PhiResult<Vec<u8>> {
+        if !is_sealed(sealed) || sealed.len() < HEADER_LEN {
+            return Err(PhiError::format("not an encrypted φ-payload"));
+        }
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(&key.0));
+        let nonce = Nonce::from_slice(&sealed[5..HEADER_LEN]);
+        cipher
+            .decrypt(nonce, Payload { msg: &sealed[HEADER_LEN..], aad })
+            .map_err(|_| PhiError::Authentication("wrong key or name, or tampered data".to_string()))
+    }
+}
+
+#[cfg(all(test, feature = "encryption"))]
+mod tests {
+    use super::*;
+    use crate::phi_bundle::{LoadOptions, PhiBundle};
+    use crate::phi_error::PhiError;
+    use crate::phi_fs::PhiMemoryStore;
+    use crate::phi_meta::PhiMetadata;
+    use std::fs;
+
+    #[test]
+    fn test_seal_open_and_tamper_detection() {
+        let key = PhiKey::generate();
+        let sealed = seal(&key, b"phi", b"name").unwrap();
+        assert!(is_sealed(&sealed));
+        assert_eq!(open(&key, &sealed, b"name").unwrap(), b"phi");
+
+        // Same plaintext never produces the same ciphertext
+        assert_ne!(seal(&key, b"phi", b"name").unwrap(), sealed);
+
+        let mut tampered = sealed.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(matches!(open(&key, &tampered, b"name"), Err(PhiError::Authentication(_))));
+        assert!(open(&key, &sealed, b"other").is_err());
+        assert!(open(&PhiKey::generate(), &sealed, b"name").is_err());
+    }
+
+    #[test]
+    fn test_encrypted_store_entries() {
+        let key = PhiKey::generate();
+        let store = PhiMemoryStore::new(".phi_crypto_store").with_encryption(key.clone());
+        store.save("secret", &[1.0, 2.0]).unwrap();
+
+        let bytes = fs::read(".phi_crypto_store/secret.bin").unwrap();
+        assert!(is_sealed(&bytes));
+        assert_eq!(store.load("secret").unwrap(), vec![1.0, 2.0]);
+
+        // Sidecars are sealed too and bound to their entry
+        store.save_meta("secret", &PhiMetadata::new(10, 0.01, 2)).unwrap();
+        let sidecar = fs::read(".phi_crypto_store/secret.meta.txt").unwrap();
+        assert!(is_sealed(&sidecar));
+        assert_eq!(store.load_meta("secret").unwrap().n, 10);
+        fs::copy(".phi_crypto_store/secret.meta.txt", ".phi_crypto_store/other.meta.txt").unwrap();
+        assert!(store.read_meta("other").is_err());
+
+        let keyless = PhiMemoryStore::new(".phi_crypto_store");
+        assert!(keyless.is_encrypted());
+        assert!(matches!(keyless.load("secret"), Err(PhiError::KeyRequired(_))));
+        assert!(keyless.load_meta("secret").is_err());
+
+        // A payload moved under another name fails authentication
+        fs::copy(".phi_crypto_store/secret.bin", ".phi_crypto_store/moved.bin").unwrap();
+        assert!(store.load("moved").is_err());
+
+        // Sealed payloads are never shared, so content addressing keeps plain entries
+        let store = store.with_content_addressing(true);
+        store.save("shared", &[1.0, 2.0]).unwrap();
+        assert!(store.read_ref("shared").unwrap().is_none());
+        assert_eq!(store.load("shared").unwrap(), vec![1.0, 2.0]);
+
+        // Sealed payloads cannot be streamed a frame at a time
+        let err = store.export_bundle_stream("secret", ".phi_crypto_store.phib").unwrap_err();
+        assert!(matches!(err, PhiError::Unsupported(_)));
+
+        // Plain entries are never silently mixed with sealed ones
+        let mut plain = PhiMemoryStore::new(".phi_crypto_plain_store");
+        plain.save("open", &[1.0]).unwrap();
+        assert!(plain.set_encryption(key).is_err());
+        assert!(!plain.is_encrypted());
+
+        fs::remove_dir_all(".phi_crypto_store").unwrap();
+        fs::remove_dir_all(".phi_crypto_plain_store").unwrap();
+    }
+
+    #[test]
+    fn test_encrypted_bundle() {
+        let key = PhiKey::generate();
+        let bundle = PhiBundle::new("sig", PhiMetadata::new(10, 0.01, 1), vec![4.2]);
+        let path = "test_bundle_encrypted.phi";
+        bundle.save_encrypted(path, &key).unwrap();
+        assert!(PhiBundle::load_json(path, &LoadOptions::default()).is_err());
+
+        let loaded = PhiBundle::load_encrypted(path, &key).unwrap();
+        assert_eq!(loaded.data, vec![4.2]);
+        assert!(PhiBundle::load_encrypted(path, &PhiKey::generate()).is_err());
+
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,336 @@
+//! phi_dedup: content-addressed payload storage for φ-stores
+//! Identical payloads are stored once under their hash; names become `.ref` files
+
+use crate::phi_error::{PhiError, PhiResult};
+use crate::phi_fs::PhiMemoryStore;
+use crate::phi_index::fnv1a64;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Directory (inside the store) holding content-addressed payloads
+pub const OBJECTS_DIR: &str = ".objects";
+/// File (inside `OBJECTS_DIR`) recording how many names refer to each object
+pub const REFCOUNTS_FILE: &str = "refcounts.json";
+
+/// Space accounting for a content-addressed store
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DedupReport {
+    /// Number of named entries (plain and content-addressed)
+    pub entries: usize,
+    /// Number of distinct payload objects
+    pub objects: usize,
+    /// Bytes the entries would take if every name had its own copy
+    pub logical_bytes: u64,
+    /// Bytes actually used by payloads on disk
+    pub stored_bytes: u64,
+    /// Objects shared by more than one name, keyed by hash
+    pub shared: BTreeMap<String, Vec<String>>,
+}
+
+impl DedupReport {
+    /// Bytes saved by deduplication
+    pub fn saved_bytes(&self) -> u64 {
+        self.logical_bytes - self.stored_bytes
+    }
+}
+
+impl PhiMemoryStore {
+    pub(crate) fn ref_path(&self, name: &str) -> PathBuf {
+        self.base_path().join(format!("{}.ref", name))
+    }
+
+    pub(crate) fn object_path(&self, hash: &str) -> PathBuf {
+        self.base_path().join(OBJECTS_DIR).join(format!("{}.bin", hash))
+    }
+
+    /// Object hash a named entry refers to, if it is content-addressed
+    pub(crate) fn read_ref(&self, name: &str) -> PhiResult<Option<String>> {
+        let path = self.ref_path(name);
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(fs::read_to_string(path)?.trim().to_string()))
+    }
+
+    /// All `.ref` entries in the store with the object each one points to
+    fn refs(&self) -> PhiResult<Vec<(String, String)>> {
+        let mut refs = Vec::new();
+        for entry in fs::read_dir(self.base_path())? {
+            let path = entry?.path();
+            if path.is_file() && path.extension().is_some_and(|ext| ext == "ref") {
+                let name = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+                refs.push((name, fs::read_to_string(&path)?.trim().to_string()));
+            }
+        }
+        Ok(refs)
+    }
+
+    /// Reference counts by object hash, counted from the `.ref` files when none are recorded
+    pub(crate) fn refcounts(&self) -> PhiResult<BTreeMap<String, usize>> {
+        let path = self.base_path().join(OBJECTS_DIR).join(REFCOUNTS_FILE);
+        if !path.exists() {
+            return self.count_refs();
+        }
+        serde_json::from_str(&fs::read_to_string(path)?).map_err(|e| PhiError::parse(REFCOUNTS_FILE, e))
+    }
+
+    fn count_refs(&self) -> PhiResult<BTreeMap<String, usize>> {
+        let mut counts = BTreeMap::new();
+        for (_, hash) in self.refs()? {
+            *counts.entry(hash).or_insert(0) += 1;
+        }
+        Ok(counts)
+    }
+
+    fn save_refcounts(&self, counts: &BTreeMap<String, usize>) -> PhiResult<()> {
+        let dir = self.base_path().join(OBJECTS_DIR);
+        fs::create_dir_all(&dir)?;
+        let tmp = dir.join(format!("{}.tmp", REFCOUNTS_FILE));
+        fs::write(&tmp, serde_json::to_string_pretty(counts)?)?;
+        fs::rename(tmp, dir.join(REFCOUNTS_FILE))?;
+        Ok(())
+    }
+
+    /// Write a payload object unless an identical one exists; returns its hash
+    pub(crate) fn store_object(&self, bytes: &[u8]) -> PhiResult<String> {
+        self.place_object(
+            fnv1a64(bytes),
+            |path| Ok(fs::read(path)? == bytes),
+            |path| {
+                let tmp = path.with_extension("tmp");
+                fs::write(&tmp, bytes)?;
+                fs::rename(tmp, path)?;
+                Ok(())
+            },
+        )
+    }
+
+    /// `store_object` for a payload already written to `file`, whose `fnv1a64` is `hash`;
+    /// the file is moved into the object store or removed
+    pub(crate) fn store_object_file(&self, file: &Path, hash: u64) -> PhiResult<String> {
+        let hash = self.place_object(hash, |path| same_contents(path, file), |path| Ok(fs::rename(file, path)?))?;
+        if file.exists() {
+            fs::remove_file(file)?;
+        }
+        Ok(hash)
+    }
+
+    /// Find the object slot for a payload hashing to `base`: `same` tells whether an
+    /// existing object holds the payload, `write` fills a free slot
+    fn place_object<S, W>(&self, base: u64, mut same: S, write: W) -> PhiResult<String>
+    where
+        S: FnMut(&Path) -> PhiResult<bool>,
+        W: FnOnce(&Path) -> PhiResult<()>,
+    {
+        fs::create_dir_all(self.base_path().join(OBJECTS_DIR))?;
+        let base = format!("{:016x}", base);
+        let mut probe = 0;
+        loop {
+            let hash = if probe == 0 { base.clone() } else { format!("{}-{}", base, probe) };
+            let path = self.object_path(&hash);
+            if !path.exists() {
+                write(&path)?;
+                return Ok(hash);
+            }
+            if same(&path)? {
+                return Ok(hash);
+            }
+            // Another payload has this hash: probe past the collision with a numeric suffix
+            probe += 1;
+        }
+    }
+
+    /// Make object `hash` the payload of `name`, releasing the one it referred to before
+    pub(crate) fn link_payload(&self, name: &str, previous_object: Option<String>, hash: &str) -> PhiResult<()> {
+        if previous_object.as_deref() != Some(hash) {
+            self.link_object(name, hash)?;
+        }
+        let bin_path = self.base_path().join(format!("{}.bin", name));
+        if bin_path.exists() {
+            fs::remove_file(bin_path)?;
+        }
+        if let Some(old) = previous_object.filter(|old| old != hash) {
+            self.release_object(&old)?;
+        }
+        Ok(())
+    }
+
+    /// Point `name` at an object and count the new reference
+    pub(crate) fn link_object(&self, name: &str, hash: &str) -> PhiResult<()> {
+        let mut counts = self.refcounts()?;
+        fs::write(self.ref_path(name), hash)?;
+        *counts.entry(hash.to_string()).or_insert(0) += 1;
+        self.save_refcounts(&counts)
+    }
+
+    /// Number of names referring to an object
+    pub fn object_refcount(&self, hash: &str) -> PhiResult<usize> {
+        Ok(self.refcounts()?.get(hash).copied().unwrap_or(0))
+    }
+
+    /// Drop one reference to an object, deleting it once nothing refers to it any more
+    pub(crate) fn release_object(&self, hash: &str) -> PhiResult<()> {
+        let mut counts = self.refcounts()?;
+        let remaining = counts.get(hash).map_or(0, |count| count.saturating_sub(1));
+        if remaining == 0 {
+            counts.remove(hash);
+            let path = self.object_path(hash);
+            if path.exists() {
+                fs::remove_file(path)?;
+            }
+        } else {
+            counts.insert(hash.to_string(), remaining);
+        }
+        self.save_refcounts(&counts)
+    }
+
+    /// Drop the `.ref` file of an entry and release its object
+    pub(crate) fn remove_data_ref(&self, name: &str) -> PhiResult<()> {
+        if let Some(hash) = self.read_ref(name)? {
+            fs::remove_file(self.ref_path(name))?;
+            self.release_object(&hash)?;
+        }
+        Ok(())
+    }
+
+    /// Remove objects no `.ref` file points to and recount the references from the `.ref`
+    /// files; returns the removed paths
+    pub fn collect_objects(&self) -> PhiResult<Vec<PathBuf>> {
+        let dir = self.base_path().join(OBJECTS_DIR);
+        let mut removed = Vec::new();
+        if !dir.is_dir() {
+            return Ok(removed);
+        }
+        let counts = self.count_refs()?;
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.file_name().is_some_and(|f| f == REFCOUNTS_FILE) {
+                continue;
+            }
+            let hash = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+            if !counts.contains_key(&hash) {
+                fs::remove_file(&path)?;
+                removed.push(path);
+            }
+        }
+        self.save_refcounts(&counts)?;
+        Ok(removed)
+    }
+
+    /// Report how much space content addressing saves in this store
+    pub fn dedup_report(&self) -> PhiResult<DedupReport> {
+        let mut report = DedupReport::default();
+        let mut by_object: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for (name, hash) in self.refs()? {
+            by_object.entry(hash).or_default().push(name);
+        }
+
+        for (hash, names) in by_object {
+            let size = fs::metadata(self.object_path(&hash))?.len();
+            report.entries += names.len();
+            report.objects += 1;
+            report.logical_bytes += size * names.len() as u64;
+            report.stored_bytes += size;
+            if names.len() > 1 {
+                let mut names = names;
+                names.sort();
+                report.shared.insert(hash, names);
+            }
+        }
+
+        // Plain entries count once each
+        for name in self.list()? {
+            if self.read_ref(&name)?.is_none() {
+                let size = fs::metadata(self.data_path(&name)?)?.len();
+                report.entries += 1;
+                report.objects += 1;
+                report.logical_bytes += size;
+                report.stored_bytes += size;
+            }
+        }
+        Ok(report)
+    }
+}
+
+/// Whether two files hold the same bytes, compared a block at a time
+fn same_contents(a: &Path, b: &Path) -> PhiResult<bool> {
+    if fs::metadata(a)?.len() != fs::metadata(b)?.len() {
+        return Ok(false);
+    }
+    let (mut a, mut b) = (fs::File::open(a)?, fs::File::open(b)?);
+    let (mut block_a, mut block_b) = (vec![0u8; 64 * 1024], vec![0u8; 64 * 1024]);
+    loop {
+        let read = a.read(&mut block_a)?;
+        if read == 0 {
+            return Ok(true);
+        }
+        b.read_exact(&mut block_b[..read])?;
+        if block_a[..read] != block_b[..read] {
+            return Ok(false);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::phi_compress::HEADER_LEN;
+    use std::path::Path;
+
+    #[test]
+    fn test_dedup_shares_identical_payloads() {
+        let store = PhiMemoryStore::new(".phi_dedup_store").with_content_addressing(true);
+        let pattern = [1.0, 2.0, 3.0];
+        store.save("a", &pattern).unwrap();
+        store.save("b", &pattern).unwrap();
+        store.save("c", &[4.0]).unwrap();
+
+        assert_eq!(store.load("b").unwrap(), pattern.to_vec());
+        let report = store.dedup_report().unwrap();
+        assert_eq!(report.entries, 3);
+        assert_eq!(report.objects, 2);
+        assert_eq!(report.saved_bytes(), 24 + HEADER_LEN as u64);
+        assert_eq!(report.shared.values().next().unwrap(), &vec!["a", "b"]);
+
+        // The shared object survives until its last reference is gone
+        let hash = store.read_ref("a").unwrap().unwrap();
+        store.delete("a").unwrap();
+        assert_eq!(store.object_refcount(&hash).unwrap(), 1);
+        assert_eq!(store.load("b").unwrap(), pattern.to_vec());
+        store.delete("b").unwrap();
+        assert!(!store.object_path(&hash).exists());
+
+        // Counts are recorded on disk and rebuilt from the `.ref` files when lost
+        store.save("d", &pattern).unwrap();
+        store.save("e", &pattern).unwrap();
+        let hash = store.read_ref("d").unwrap().unwrap();
+        fs::remove_file(".phi_dedup_store/.objects/refcounts.json").unwrap();
+        assert_eq!(store.object_refcount(&hash).unwrap(), 2);
+        store.collect_objects().unwrap();
+        assert!(Path::new(".phi_dedup_store/.objects/refcounts.json").exists());
+        assert_eq!(store.object_refcount(&hash).unwrap(), 2);
+
+        // Reopening the store keeps deduplicating
+        let reopened = PhiMemoryStore::new(".phi_dedup_store");
+        assert!(reopened.is_content_addressed());
+        reopened.save("f", &pattern).unwrap();
+        assert_eq!(reopened.object_refcount(&hash).unwrap(), 3);
+
+        fs::remove_dir_all(".phi_dedup_store").unwrap();
+    }
+
+    #[test]
+    fn test_overwrite_releases_previous_object() {
+        let store = PhiMemoryStore::new(".phi_dedup_overwrite_store").with_content_addressing(true);
+        store.save("a", &[1.0]).unwrap();
+        let old = store.read_ref("a").unwrap().unwrap();
+        store.save("a", &[2.0]).unwrap();
+        assert!(!store.object_path(&old).exists());
+        assert_eq!(store.load("a").unwrap(), vec![2.0]);
+        assert_eq!(store.list().unwrap(), vec!["a"]);
+
+        fs::remove_dir_all(".phi_dedup_overwrite_store").unwrap();
+    }
+}
@@ -0,0 +1,135 @@
+//! phi_error: crate-wide error type
+//! Returned by store, metadata, bundle and storage operations instead of panicking
+
+use crate::core::PHI_TABLE;
+use crate::phi_meta::MetaFormat;
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum PhiError {
+    /// File-system or stream failure
+    Io(std::io::Error),
+    /// Text, JSON or TOML that does not parse; the parser's error is the source
+    Parse {
+        what: &'static str,
+        source: Box<dyn Error + Send + Sync>,
+    },
+    /// Bytes that are not in the expected format or version
+    Format(String),
+    /// Well-formed data that fails a consistency check
+    Validation(String),
+    /// φ-approximation order outside `1..=PHI_TABLE.len()`
+    InvalidOrder { n: usize },
+    /// Named entry, revision or snapshot that does not exist
+    NotFound(String),
+    /// Metadata key that must be present but is not
+    MissingKey { key: &'static str },
+    /// Metadata key set more than once; lines are 1-based
+    DuplicateKey { key: String, line: usize, first_line: usize },
+    /// Metadata value that does not parse or is out of range
+    Malformed { line: usize, key: String, reason: String },
+    /// Metadata key this build does not know
+    UnknownKey { line: usize, key: String },
+    /// JSON/TOML metadata that does not deserialize
+    Syntax { format: MetaFormat, line: usize, message: String },
+    /// Record or bundle written by a newer release than this build understands
+    UnsupportedSchema { version: u32, supported: u32 },
+    /// Codec or feature that is not compiled into this build
+    Unsupported(String),
+    /// Encrypted entry or file and no key to open it
+    KeyRequired(String),
+    /// Sealed bytes that fail authentication: wrong key, wrong name or tampered data
+    Authentication(String),
+}
+
+pub type PhiResult<T> = Result<T, PhiError>;
+
+impl PhiError {
+    pub(crate) fn parse<E: Error + Send + Sync + 'static>(what: &'static str, err: E) -> Self {
+        PhiError::Parse { what, source: Box::new(err) }
+    }
+
+    pub(crate) fn format(message: impl Into<String>) -> Self {
+        PhiError::Format(message.into())
+    }
+
+    /// Fail with `InvalidOrder` unless `n` indexes into `PHI_TABLE`
+    pub fn check_order(n: usize) -> PhiResult<()> {
+        match (1..=PHI_TABLE.len()).contains(&n) {
+            true => Ok(()),
+            false => Err(PhiError::InvalidOrder { n }),
+        }
+    }
+}
+
+impl fmt::Display for PhiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PhiError::Io(err) => write!(f, "I/O error: {}", err),
+            PhiError::Parse { what, source } => write!(f, "cannot parse {}: {}", what, source),
+            PhiError::Format(message) => write!(f, "bad format: {}", message),
+            PhiError::Validation(message) => write!(f, "validation failed: {}", message),
+            PhiError::InvalidOrder { n } => write!(f, "order n = {} is outside 1..={}", n, PHI_TABLE.len()),
+            PhiError::NotFound(what) => write!(f, "not found: {}", what),
+            PhiError::MissingKey { key } => write!(f, "missing key '{}'", key),
+            PhiError::DuplicateKey { key, line, first_line } => {
+                write!(f, "line {}: duplicate key '{}' (first set on line {})", line, key, first_line)
+            }
+            PhiError::Malformed { line, key, reason } => write!(f, "line {}: bad '{}': {}", line, key, reason),
+            PhiError::UnknownKey { line, key } => write!(f, "line {}: unknown key '{}'", line, key),
+            PhiError::Syntax { format, line, message } => {
+                write!(f, "line {}: invalid {} metadata: {}", line, format, message)
+            }
+            PhiError::UnsupportedSchema { version, supported } => {
+                write!(f, "schema version {} is newer than supported version {}", version, supported)
+            }
+            PhiError::Unsupported(what) => write!(f, "not supported by this build: {}", what),
+            PhiError::KeyRequired(what) => write!(f, "{} is encrypted and no key is configured", what),
+            PhiError::Authentication(what) => write!(f, "authentication failed: {}", what),
+        }
+    }
+}
+
+impl Error for PhiError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PhiError::Io(err) => Some(err),
+            PhiError::Parse { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for PhiError {
+    fn from(err: std::io::Error) -> Self {
+        PhiError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for PhiError {
+    fn from(err: serde_json::Error) -> Self {
+        PhiError::parse("JSON", err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_sources_chain() {
+        let err: PhiError = serde_json::from_str::<Vec<f64>>("[1,").unwrap_err().into();
+        assert!(matches!(err, PhiError::Parse { what: "JSON", .. }));
+        assert!(err.source().unwrap().downcast_ref::<serde_json::Error>().is_some());
+
+        let (_, issues) = crate::phi_meta::PhiMetadata::parse("step=0.01\nlength=1\nsaved_at=2025-05-05T00:00:00Z\n");
+        assert!(matches!(&issues[..], [PhiError::MissingKey { key: "n" }]));
+        assert_eq!(issues[0].to_string(), "missing key 'n'");
+
+        let io = std::io::Error::other("disk");
+        assert_eq!(PhiError::from(io).source().unwrap().to_string(), "disk");
+        assert!(matches!(PhiError::check_order(0), Err(PhiError::InvalidOrder { n: 0 })));
+        assert!(PhiError::check_order(PHI_TABLE.len()).is_ok());
+    }
+}
@@ -1,21 +1,194 @@
 //! φ-fs: named storage for quantized φ-memories
 //! Save/load sets of φ-encoded data with labels
 
-use std::fs::{File, create_dir_all};
-use std::io::{BufWriter, BufReader, Write, Read};
+use crate::phi_error::{PhiError, PhiResult};
+use crate::phi_compress::{decode_payload, detect_compression, encode_payload, payload_len, Compression};
+#[cfg(feature = "encryption")]
+use crate::phi_crypto::PhiKey;
+use crate::phi_index::{phi_checksum, ManifestEntry, PhiManifest, MANIFEST_FILE};
+use crate::phi_meta::{read_meta_bytes, write_meta_bytes, MetaFormat, PhiMetadata};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::fs::create_dir_all;
 use std::path::{Path, PathBuf};
 
+/// File holding the settings a store keeps across reopening
+pub const STORE_CONFIG_FILE: &str = "store.json";
+
+/// Store settings persisted in `STORE_CONFIG_FILE`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct StoreConfig {
+    #[serde(default)]
+    versioned: bool,
+    #[serde(default)]
+    encrypted: bool,
+    #[serde(default)]
+    content_addressed: bool,
+}
+
+impl StoreConfig {
+    fn load(base_path: &Path) -> PhiResult<Self> {
+        let path = base_path.join(STORE_CONFIG_FILE);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Ok(serde_json::from_slice(&std::fs::read(path)?)?)
+    }
+
+    fn save(&self, base_path: &Path) -> PhiResult<()> {
+        let tmp = base_path.join(format!("{}.tmp", STORE_CONFIG_FILE));
+        std::fs::write(&tmp, serde_json::to_string_pretty(self)?)?;
+        std::fs::rename(tmp, base_path.join(STORE_CONFIG_FILE))?;
+        Ok(())
+    }
+}
+
+/// Associated data binding a sealed sidecar to its entry, distinct from the payload's
+fn meta_aad(name: &str) -> Vec<u8> {
+    format!("{}.meta", name).into_bytes()
+}
+
 /// Store for named φ-memories
 pub struct PhiMemoryStore {
     base_path: PathBuf,
+    versioned: bool,
+    encrypted: bool,
+    content_addressed: bool,
+    compression: Compression,
+    meta_format: MetaFormat,
+    #[cfg(feature = "encryption")]
+    key: Option<PhiKey>,
 }
 
 impl PhiMemoryStore {
-    /// Create store under given directory
+    /// Create store under given directory, picking up the settings it was saved with
+    ///
+    /// Panics if the directory cannot be created or its settings cannot be read; use `open`
+    /// to handle those failures.
     pub fn new<P: AsRef<Path>>(base_path: P) -> Self {
+        Self::open(base_path).expect("failed to open φ-store")
+    }
+
+    /// Open or create a store under given directory, picking up the settings it was saved with
+    pub fn open<P: AsRef<Path>>(base_path: P) -> PhiResult<Self> {
         let path = base_path.as_ref();
-        create_dir_all(path).expect("failed to create φ-store directory");
-        Self { base_path: path.to_path_buf() }
+        create_dir_all(path)?;
+        let config = StoreConfig::load(path)?;
+        Ok(Self {
+            base_path: path.to_path_buf(),
+            versioned: config.versioned,
+            encrypted: config.encrypted,
+            content_addressed: config.content_addressed,
+            compression: Compression::None,
+            meta_format: MetaFormat::Text,
+            #[cfg(feature = "encryption")]
+            key: None,
+        })
+    }
+
+    /// Keep every overwritten revision of an entry (see `history`/`load_version`)
+    ///
+    /// The setting is saved with the store, so reopening it keeps history too.
+    pub fn with_versioning(mut self, enabled: bool) -> Self {
+        self.set_versioning(enabled).expect("failed to save φ-store settings");
+        self
+    }
+
+    /// Turn history on or off and save the setting with the store
+    pub fn set_versioning(&mut self, enabled: bool) -> PhiResult<()> {
+        StoreConfig { versioned: enabled, ..self.config() }.save(&self.base_path)?;
+        self.versioned = enabled;
+        Ok(())
+    }
+
+    /// Settings of this store as saved in `STORE_CONFIG_FILE`
+    fn config(&self) -> StoreConfig {
+        StoreConfig {
+            versioned: self.versioned,
+            encrypted: self.encrypted,
+            content_addressed: self.content_addressed,
+        }
+    }
+
+    pub fn is_versioned(&self) -> bool {
+        self.versioned
+    }
+
+    /// Store payloads by content hash so identical data is kept once (see `dedup_report`)
+    ///
+    /// Encrypted stores save plain entries instead: every sealed payload has its own nonce
+    /// and is bound to its name, so no two of them could ever be shared. The setting is saved
+    /// with the store, so reopening it keeps deduplicating.
+    pub fn with_content_addressing(mut self, enabled: bool) -> Self {
+        self.set_content_addressing(enabled).expect("failed to save φ-store settings");
+        self
+    }
+
+    /// Turn content addressing on or off and save the setting with the store
+    pub fn set_content_addressing(&mut self, enabled: bool) -> PhiResult<()> {
+        StoreConfig { content_addressed: enabled, ..self.config() }.save(&self.base_path)?;
+        self.content_addressed = enabled;
+        Ok(())
+    }
+
+    pub fn is_content_addressed(&self) -> bool {
+        self.content_addressed
+    }
+
+    /// Compress payloads written by `save` with the given codec
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    /// Write metadata sidecars in the given format (loads detect any format)
+    pub fn with_meta_format(mut self, format: MetaFormat) -> Self {
+        self.meta_format = format;
+        self
+    }
+
+    pub fn meta_format(&self) -> MetaFormat {
+        self.meta_format
+    }
+
+    /// Encrypt payloads and metadata sidecars with ChaCha20-Poly1305 under the given key
+    ///
+    /// Each write uses a fresh nonce and the entry name as associated data, so a file
+    /// copied under another name fails to load. The store remembers that it is encrypted,
+    /// so opening it again without a key fails instead of reading sealed bytes as data.
+    #[cfg(feature = "encryption")]
+    pub fn with_encryption(mut self, key: PhiKey) -> Self {
+        self.set_encryption(key).expect("failed to enable φ-store encryption");
+        self
+    }
+
+    /// Use `key` for this store, marking it as encrypted if it is not yet
+    ///
+    /// Entries already written in plain form are not converted, so an unencrypted store
+    /// that holds data is refused.
+    #[cfg(feature = "encryption")]
+    pub fn set_encryption(&mut self, key: PhiKey) -> PhiResult<()> {
+        if !self.encrypted {
+            if !self.list()?.is_empty() {
+                return Err(PhiError::Validation(format!(
+                    "'{}' holds unencrypted entries; encryption must be enabled on an empty store",
+                    self.base_path.display()
+                )));
+            }
+            StoreConfig { encrypted: true, ..self.config() }.save(&self.base_path)?;
+            self.encrypted = true;
+        }
+        self.key = Some(key);
+        Ok(())
+    }
+
+    /// Whether entries of this store are sealed, with or without a key configured
+    pub fn is_encrypted(&self) -> bool {
+        self.encrypted
     }
 
     pub fn base_path(&self) -> &std::path::Path {
@@ -23,38 +196,310 @@ impl PhiMemoryStore {
     }
     
     /// Save named φ-data
-    pub fn save(&self, name: &str, data: &[f64]) -> std::io::Result<()> {
-        let path = self.base_path.join(format!("{}.bin", name));
-        let mut writer = BufWriter::new(File::create(path)?);
-        for &val in data {
-            writer.write_all(&val.to_le_bytes())?;
+    pub fn save(&self, name: &str, data: &[f64]) -> PhiResult<()> {
+        self.save_with_compression(name, data, self.compression)
+    }
+
+    /// Save named φ-data with a codec chosen for this entry only
+    pub fn save_with_compression(&self, name: &str, data: &[f64], compression: Compression) -> PhiResult<()> {
+        let (manifest, revision) = self.begin_write(name)?;
+        let previous_object = self.read_ref(name)?;
+        let bin_path = self.base_path.join(format!("{}.bin", name));
+        let bytes = self.seal_payload(name, encode_payload(data, compression)?)?;
+        if self.content_addressed && !self.is_encrypted() {
+            let hash = self.store_object(&bytes)?;
+            self.link_payload(name, previous_object, &hash)?;
+        } else {
+            std::fs::write(bin_path, &bytes)?;
+            if previous_object.is_some() {
+                self.remove_data_ref(name)?;
+            }
+        }
+        self.finish_write(manifest, name, revision, data.len(), phi_checksum(data), compression)
+    }
+
+    /// Current revision of `name`, archived first when the store keeps history
+    pub(crate) fn begin_write(&self, name: &str) -> PhiResult<(PhiManifest, u32)> {
+        let manifest = self.manifest()?;
+        let exists = self.data_path(name)?.exists();
+        let revision = match manifest.get(name) {
+            Some(entry) => entry.revision,
+            None if exists => self.next_archived_revision(name)?,
+            None => 0,
+        };
+        // Data written before revisions were tracked counts as revision 1
+        let revision = if exists { revision.max(1) } else { 0 };
+        if self.versioned && exists {
+            self.archive_revision(name, revision)?;
+        }
+        Ok((manifest, revision))
+    }
+
+    /// Record a payload written after `begin_write` in the manifest
+    pub(crate) fn finish_write(
+        &self,
+        mut manifest: PhiManifest,
+        name: &str,
+        revision: u32,
+        length: usize,
+        checksum: u64,
+        compression: Compression,
+    ) -> PhiResult<()> {
+        let now = Utc::now();
+        // Metadata saved before the data had no record to go into yet
+        let sidecar = match manifest.entries.contains_key(name) {
+            true => None,
+            false => self.read_meta(name).ok(),
+        };
+        let entry = manifest.entries.entry(name.to_string()).or_insert_with(|| ManifestEntry {
+            name: name.to_string(),
+            length: 0,
+            n: sidecar.as_ref().map(|m| m.n),
+            step: sidecar.as_ref().map(|m| m.step),
+            checksum: 0,
+            created_at: now,
+            updated_at: now,
+            expires_at: None,
+            revision: 0,
+            compression: Compression::None,
+            tags: sidecar.as_ref().map(|m| m.tags.clone()).unwrap_or_default(),
+            route_threshold: sidecar.and_then(|m| m.route_threshold),
+        });
+        entry.compression = compression;
+        entry.revision = revision + 1;
+        entry.length = length;
+        entry.checksum = checksum;
+        entry.updated_at = now;
+        manifest.save(&self.base_path)
+    }
+
+    /// Save metadata for a named φ-memory and record it in the manifest
+    pub fn save_meta(&self, name: &str, meta: &PhiMetadata) -> PhiResult<()> {
+        PhiError::check_order(meta.n)?;
+        self.write_meta(name, meta, self.meta_format)?;
+        let mut manifest = self.manifest()?;
+        if let Some(entry) = manifest.entries.get_mut(name) {
+            entry.n = Some(meta.n);
+            entry.step = Some(meta.step);
+            entry.tags = meta.tags.clone();
+            entry.route_threshold = meta.route_threshold;
+            entry.updated_at = Utc::now();
+            manifest.save(&self.base_path)?;
         }
         Ok(())
     }
 
+    /// Load metadata of a named entry strictly and check its length against the stored data
+    pub fn load_meta(&self, name: &str) -> PhiResult<PhiMetadata> {
+        let meta = self.read_meta(name)?;
+        let bytes = self.read_payload(name, &self.existing_data_path(name)?)?;
+        meta.validate_length(payload_len(&bytes))?;
+        Ok(meta)
+    }
+
+    /// Load named φ-data and reconstruct the original values from the recorded encoding spec
+    pub fn load_decoded(&self, name: &str) -> PhiResult<Vec<f64>> {
+        let meta = self.load_meta(name)?;
+        meta.decode(&self.load(name)?)
+    }
+
     /// Load named φ-data
-    pub fn load(&self, name: &str) -> std::io::Result<Vec<f64>> {
-        let path = self.base_path.join(format!("{}.bin", name));
-        let mut reader = BufReader::new(File::open(path)?);
-        let mut buf = [0u8; 8];
-        let mut data = Vec::new();
-        while reader.read_exact(&mut buf).is_ok() {
-            data.push(f64::from_le_bytes(buf));
-        }
-        Ok(data)
+    pub fn load(&self, name: &str) -> PhiResult<Vec<f64>> {
+        decode_payload(&self.read_payload(name, &self.existing_data_path(name)?)?)
     }
 
-    /// List all stored φ-memory names
-    pub fn list(&self) -> std::io::Result<Vec<String>> {
+    /// Delete named φ-data together with its metadata and revision history
+    pub fn delete(&self, name: &str) -> PhiResult<()> {
+        self.existing_data_path(name)?;
+        self.remove_data(name)?;
+        PhiMetadata::remove(name, &self.base_path)?;
+        self.remove_history(name)?;
+        let mut manifest = self.manifest()?;
+        manifest.entries.remove(name);
+        manifest.save(&self.base_path)
+    }
+
+    /// Whether a named entry has stored data
+    pub fn contains(&self, name: &str) -> PhiResult<bool> {
+        Ok(self.data_path(name)?.exists())
+    }
+
+    /// List all stored φ-memory names in sorted order, whatever the filesystem's `read_dir` order
+    pub fn list(&self) -> PhiResult<Vec<String>> {
         let mut entries = Vec::new();
         for entry in std::fs::read_dir(&self.base_path)? {
-            let entry = entry?;
-            if let Some(stem) = entry.path().file_stem() {
-                entries.push(stem.to_string_lossy().to_string());
+            let path = entry?.path();
+            let is_data = path.extension().is_some_and(|ext| ext == "bin" || ext == "ref");
+            if path.is_file() && is_data {
+                if let Some(stem) = path.file_stem() {
+                    entries.push(stem.to_string_lossy().to_string());
+                }
             }
         }
+        entries.sort();
+        entries.dedup();
         Ok(entries)
     }
+
+    /// Path of the file holding the payload of a named entry
+    ///
+    /// For content-addressed entries this is the shared object the `.ref` file points to.
+    pub fn data_path(&self, name: &str) -> PhiResult<PathBuf> {
+        match self.read_ref(name)? {
+            Some(hash) => Ok(self.object_path(&hash)),
+            None => Ok(self.base_path.join(format!("{}.bin", name))),
+        }
+    }
+
+    /// Payload path of an entry that exists, `PhiError::NotFound` otherwise
+    pub(crate) fn existing_data_path(&self, name: &str) -> PhiResult<PathBuf> {
+        let path = self.data_path(name)?;
+        match path.exists() {
+            true => Ok(path),
+            false => Err(PhiError::NotFound(name.to_string())),
+        }
+    }
+
+    /// Error for an encrypted store that cannot seal or open `name`
+    fn missing_key(&self, name: &str) -> PhiError {
+        match cfg!(feature = "encryption") {
+            true => PhiError::KeyRequired(format!("'{}'", name)),
+            false => PhiError::Unsupported(format!("'{}' is encrypted (enable the `encryption` feature)", name)),
+        }
+    }
+
+    /// Seal `bytes` written for `name` when the store is encrypted
+    fn seal_bytes(&self, name: &str, aad: &[u8], bytes: Vec<u8>) -> PhiResult<Vec<u8>> {
+        if !self.encrypted {
+            return Ok(bytes);
+        }
+        #[cfg(feature = "encryption")]
+        if let Some(key) = &self.key {
+            return crate::phi_crypto::seal(key, &bytes, aad);
+        }
+        let _ = aad;
+        Err(self.missing_key(name))
+    }
+
+    /// Open `bytes` written for `name` when the store is encrypted
+    fn open_bytes(&self, name: &str, aad: &[u8], bytes: Vec<u8>) -> PhiResult<Vec<u8>> {
+        if !self.encrypted {
+            return Ok(bytes);
+        }
+        #[cfg(feature = "encryption")]
+        if let Some(key) = &self.key {
+            return crate::phi_crypto::open(key, &bytes, aad);
+        }
+        let _ = aad;
+        Err(self.missing_key(name))
+    }
+
+    /// Encrypt an encoded payload for `name` when the store is encrypted
+    fn seal_payload(&self, name: &str, bytes: Vec<u8>) -> PhiResult<Vec<u8>> {
+        self.seal_bytes(name, name.as_bytes(), bytes)
+    }
+
+    /// Read a payload file written for `name`, decrypting it when the store is encrypted
+    pub(crate) fn read_payload(&self, name: &str, path: &Path) -> PhiResult<Vec<u8>> {
+        self.open_bytes(name, name.as_bytes(), std::fs::read(path)?)
+    }
+
+    /// Write metadata of a named entry as a `format` sidecar, sealed when the store is encrypted
+    pub(crate) fn write_meta(&self, name: &str, meta: &PhiMetadata, format: MetaFormat) -> PhiResult<()> {
+        let content = self.seal_bytes(name, &meta_aad(name), meta.render(format)?.into_bytes())?;
+        write_meta_bytes(name, &self.base_path, format, &content)
+    }
+
+    /// Read sidecar `stem` in `dir` written for `name`; returns it upgraded with its stored version
+    pub(crate) fn read_meta_in(&self, name: &str, dir: &Path, stem: &str) -> PhiResult<(PhiMetadata, u32)> {
+        let (bytes, format) = read_meta_bytes(stem, dir)?;
+        let bytes = self.open_bytes(name, &meta_aad(name), bytes)?;
+        let text = String::from_utf8(bytes).map_err(|e| PhiError::parse("metadata", e))?;
+        PhiMetadata::from_sidecar(&text, format)
+    }
+
+    /// Metadata of a named entry as stored, without checking it against the data
+    pub(crate) fn read_meta(&self, name: &str) -> PhiResult<PhiMetadata> {
+        Ok(self.read_meta_in(name, &self.base_path, name)?.0)
+    }
+
+    /// Remove the payload of a named entry, releasing its shared object if needed
+    pub(crate) fn remove_data(&self, name: &str) -> PhiResult<()> {
+        if self.ref_path(name).exists() {
+            self.remove_data_ref(name)
+        } else {
+            std::fs::remove_file(self.base_path.join(format!("{}.bin", name)))?;
+            Ok(())
+        }
+    }
+
+    /// Read the store manifest, rebuilding it with `reindex` if a store with data has none
+    pub fn manifest(&self) -> PhiResult<PhiManifest> {
+        if !self.base_path.join(MANIFEST_FILE).exists() && !self.list()?.is_empty() {
+            return self.reindex();
+        }
+        PhiManifest::load(&self.base_path)
+    }
+
+    /// Query manifest records without opening any data files
+    pub fn query<F: Fn(&ManifestEntry) -> bool>(&self, pred: F) -> PhiResult<Vec<ManifestEntry>> {
+        Ok(self.manifest()?.query(pred))
+    }
+
+    /// Convert every metadata sidecar to `format`; returns the names that were rewritten
+    pub fn migrate_metadata(&self, format: MetaFormat) -> PhiResult<Vec<String>> {
+        let mut migrated = Vec::new();
+        for name in self.list()? {
+            match PhiMetadata::detect_format(&name, &self.base_path) {
+                Some(current) if current != format => {
+                    self.write_meta(&name, &self.read_meta(&name)?, format)?;
+                    migrated.push(name);
+                }
+                _ => {}
+            }
+        }
+        Ok(migrated)
+    }
+
+    /// Rebuild the manifest from the data and metadata files on disk
+    pub fn reindex(&self) -> PhiResult<PhiManifest> {
+        let previous = PhiManifest::load(&self.base_path).unwrap_or_default();
+        let mut manifest = PhiManifest::default();
+        let now = Utc::now();
+
+        for name in self.list()? {
+            let bytes = self.read_payload(&name, &self.data_path(&name)?)?;
+            let data = decode_payload(&bytes)?;
+            let meta = self.read_meta(&name).ok();
+            let old = previous.get(&name);
+            let created_at = old
+                .map(|e| e.created_at)
+                .or(meta.as_ref().map(|m| m.saved_at))
+                .unwrap_or(now);
+            let entry = ManifestEntry {
+                name: name.clone(),
+                length: data.len(),
+                n: meta.as_ref().map(|m| m.n),
+                step: meta.as_ref().map(|m| m.step),
+                checksum: phi_checksum(&data),
+                created_at,
+                updated_at: meta.as_ref().map(|m| m.saved_at).unwrap_or(now),
+                expires_at: old.and_then(|e| e.expires_at),
+                revision: self.next_archived_revision(&name)?,
+                compression: detect_compression(&bytes),
+                tags: meta
+                    .as_ref()
+                    .map(|m| m.tags.clone())
+                    .or(old.map(|e| e.tags.clone()))
+                    .unwrap_or_default(),
+                route_threshold: meta.as_ref().and_then(|m| m.route_threshold),
+            };
+            manifest.entries.insert(name, entry);
+        }
+
+        manifest.save(&self.base_path)?;
+        Ok(manifest)
+    }
 }
 
 #[cfg(test)]
@@ -63,6 +508,7 @@ mod tests {
     use std::fs;
 
     #[test]
+    #[allow(clippy::approx_constant)]
     fn test_phi_fs_cycle() {
         let store = PhiMemoryStore::new(".phi_test_store");
         let name = "testwave";
@@ -81,4 +527,58 @@ mod tests {
 
         fs::remove_dir_all(".phi_test_store").unwrap();
     }
+
+    #[test]
+    fn test_open_reports_failures() {
+        fs::write(".phi_test_not_a_dir", b"").unwrap();
+        assert!(matches!(PhiMemoryStore::open(".phi_test_not_a_dir"), Err(PhiError::Io(_))));
+        fs::remove_file(".phi_test_not_a_dir").unwrap();
+
+        fs::create_dir_all(".phi_test_open_store").unwrap();
+        fs::write(Path::new(".phi_test_open_store").join(STORE_CONFIG_FILE), b"{").unwrap();
+        assert!(matches!(PhiMemoryStore::open(".phi_test_open_store"), Err(PhiError::Parse { .. })));
+        fs::remove_dir_all(".phi_test_open_store").unwrap();
+    }
+
+    #[test]
+    fn test_load_meta_checks_length() {
+        let store = PhiMemoryStore::new(".phi_test_meta_store");
+        store.save("wave", &[1.0, 2.0]).unwrap();
+        store.save_meta("wave", &PhiMetadata::new(10, 0.01, 2)).unwrap();
+        assert_eq!(store.load_meta("wave").unwrap().length, 2);
+
+        store.save("wave", &[1.0, 2.0, 3.0]).unwrap();
+        let err = store.load_meta("wave").unwrap_err();
+        assert!(matches!(err, PhiError::Validation(_)));
+        assert_eq!(err.to_string(), "validation failed: metadata says length=2 but 3 values are stored");
+
+        assert!(matches!(store.load("missing"), Err(PhiError::NotFound(_))));
+        assert!(matches!(store.delete("missing"), Err(PhiError::NotFound(_))));
+        let bad_order = PhiMetadata::new(0, 0.01, 3);
+        assert!(matches!(store.save_meta("wave", &bad_order), Err(PhiError::InvalidOrder { n: 0 })));
+
+        fs::remove_dir_all(".phi_test_meta_store").unwrap();
+    }
+
+    #[test]
+    fn test_meta_format_and_migration() {
+        let store = PhiMemoryStore::new(".phi_test_format_store").with_meta_format(MetaFormat::Json);
+        store.save("a", &[1.0]).unwrap();
+        store.save_meta("a", &PhiMetadata::new(10, 0.01, 1)).unwrap();
+        store.save("b", &[2.0]).unwrap();
+        PhiMetadata::new(10, 0.01, 1).save("b", store.base_path()).unwrap();
+
+        assert!(Path::new(".phi_test_format_store/a.meta.json").exists());
+        assert_eq!(store.load_meta("b").unwrap().n, 10);
+
+        let migrated = store.migrate_metadata(MetaFormat::Toml).unwrap();
+        assert_eq!(migrated.len(), 2);
+        assert_eq!(PhiMetadata::detect_format("b", store.base_path()), Some(MetaFormat::Toml));
+        assert!(!Path::new(".phi_test_format_store/b.meta.txt").exists());
+
+        store.delete("a").unwrap();
+        assert_eq!(PhiMetadata::detect_format("a", store.base_path()), None);
+
+        fs::remove_dir_all(".phi_test_format_store").unwrap();
+    }
 }
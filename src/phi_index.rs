@@ -0,0 +1,171 @@
+//! phi_index: store-level manifest for fast listing and metadata queries
+//! Keeps one record per φ-memory so lookups don't touch the data files
+
+use crate::phi_compress::Compression;
+use crate::phi_error::PhiResult;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// File name of the manifest inside a store directory
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// Manifest record for a single named φ-memory
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub name: String,
+    pub length: usize,
+    pub n: Option<usize>,
+    pub step: Option<f64>,
+    pub checksum: u64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub revision: u32,
+    #[serde(default)]
+    pub compression: Compression,
+    #[serde(default)]
+    pub tags: BTreeMap<String, String>,
+    /// `route_threshold` from the entry's metadata
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub route_threshold: Option<f64>,
+}
+
+/// Index of every entry in a φ-store, keyed by name
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PhiManifest {
+    pub entries: BTreeMap<String, ManifestEntry>,
+}
+
+impl PhiManifest {
+    /// Load manifest from store directory (empty if none was written yet)
+    pub fn load<P: AsRef<Path>>(base_path: P) -> PhiResult<Self> {
+        let path = base_path.as_ref().join(MANIFEST_FILE);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let json = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    /// Save manifest atomically: write a temp file, then rename over the old one
+    pub fn save<P: AsRef<Path>>(&self, base_path: P) -> PhiResult<()> {
+        let path = base_path.as_ref().join(MANIFEST_FILE);
+        let tmp = base_path.as_ref().join(format!("{}.tmp", MANIFEST_FILE));
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(&tmp, json)?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    /// Get record for a named entry
+    pub fn get(&self, name: &str) -> Option<&ManifestEntry> {
+        self.entries.get(name)
+    }
+
+    /// Entry names in sorted order
+    pub fn names(&self) -> Vec<String> {
+        self.entries.keys().cloned().collect()
+    }
+
+    /// Select records matching a predicate
+    pub fn query<F: Fn(&ManifestEntry) -> bool>(&self, pred: F) -> Vec<ManifestEntry> {
+        self.entries.values().filter(|e| pred(e)).cloned().collect()
+    }
+}
+
+/// FNV-1a checksum over raw bytes
+pub fn fnv1a64(bytes: &[u8]) -> u64 {
+    let mut hasher = Fnv1a64::new();
+    hasher.update(bytes);
+    hasher.finish()
+}
+
+/// Incremental form of `fnv1a64`, for data read in chunks
+#[derive(Debug, Clone, Copy)]
+pub struct Fnv1a64(u64);
+
+impl Fnv1a64 {
+    pub fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 ^= b as u64;
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+impl Default for Fnv1a64 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Checksum of a φ-sequence as it is laid out on disk (little-endian f64)
+pub fn phi_checksum(data: &[f64]) -> u64 {
+    let bytes: Vec<u8> = data.iter().flat_map(|v| v.to_le_bytes()).collect();
+    fnv1a64(&bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::phi_fs::PhiMemoryStore;
+    use crate::phi_meta::PhiMetadata;
+    use std::fs;
+
+    #[test]
+    fn test_manifest_tracks_save_and_delete() {
+        let store = PhiMemoryStore::new(".phi_index_store");
+        store.save("a", &[1.0, 2.0]).unwrap();
+        store.save("b", &[1.0, 2.0, 3.0]).unwrap();
+        let mut meta = PhiMetadata::new(10, 0.01, 3);
+        meta.tags.insert("kind".to_string(), "ramp".to_string());
+        store.save_meta("b", &meta).unwrap();
+
+        let manifest = store.manifest().unwrap();
+        assert_eq!(manifest.names(), vec!["a", "b"]);
+        assert_eq!(manifest.get("b").unwrap().n, Some(10));
+        assert_eq!(manifest.get("a").unwrap().n, None);
+        assert_eq!(manifest.get("b").unwrap().tags["kind"], "ramp");
+
+        let long = store.query(|e| e.length > 2).unwrap();
+        assert_eq!(long.len(), 1);
+        assert_eq!(long[0].name, "b");
+
+        store.delete("a").unwrap();
+        assert!(store.manifest().unwrap().get("a").is_none());
+
+        fs::remove_dir_all(".phi_index_store").unwrap();
+    }
+
+    #[test]
+    fn test_reindex_rebuilds_from_disk() {
+        let store = PhiMemoryStore::new(".phi_reindex_store");
+        store.save("wave", &[0.5, 0.25]).unwrap();
+        let checksum = store.manifest().unwrap().get("wave").unwrap().checksum;
+
+        fs::remove_file(".phi_reindex_store/manifest.json").unwrap();
+        let rebuilt = store.reindex().unwrap();
+        let entry = rebuilt.get("wave").unwrap();
+        assert_eq!(entry.length, 2);
+        assert_eq!(entry.checksum, checksum);
+
+        // Stores written before the manifest existed are indexed on first use
+        fs::remove_file(".phi_reindex_store/manifest.json").unwrap();
+        assert_eq!(store.query(|e| e.length == 2).unwrap().len(), 1);
+        assert!(store.base_path().join("manifest.json").exists());
+
+        fs::remove_dir_all(".phi_reindex_store").unwrap();
+    }
+}
@@ -0,0 +1,214 @@
+//! phi_lsh: locality-sensitive hashing over quantized φ-codes
+//! Narrows routing to a few candidate entries instead of scanning a whole pattern library
+
+use crate::phi_error::PhiResult;
+use crate::phi_index::{phi_checksum, Fnv1a64};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+
+/// File the index is persisted to inside a store directory
+pub const LSH_INDEX_FILE: &str = "lsh_index.json";
+
+/// Shape of an LSH index
+///
+/// Each table hashes the φ-codes at `positions` sampled positions, so an entry is found if it
+/// agrees with the query at every sampled position of at least one table. More tables raise
+/// recall; more positions make buckets smaller and queries faster.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LshConfig {
+    pub tables: usize,
+    pub positions: usize,
+    /// Width of a φ-code; `None` picks the smallest recorded step
+    pub quantum: Option<f64>,
+    pub seed: u64,
+}
+
+impl Default for LshConfig {
+    fn default() -> Self {
+        Self { tables: 8, positions: 4, quantum: None, seed: 0x9e37_79b9_7f4a_7c15 }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct LshTable {
+    /// Sampled positions, taken modulo the sequence length
+    positions: Vec<u32>,
+    buckets: BTreeMap<u64, Vec<String>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct LshEntry {
+    /// `phi_checksum` of the indexed values, to spot entries that changed since
+    checksum: u64,
+    /// Bucket key in each table
+    keys: Vec<u64>,
+}
+
+/// Bit-sampling LSH index mapping φ-sequences to candidate entry names
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PhiLshIndex {
+    /// Config the index was built with; missing in indexes saved before it was recorded
+    #[serde(default)]
+    config: Option<LshConfig>,
+    quantum: f64,
+    tables: Vec<LshTable>,
+    entries: BTreeMap<String, LshEntry>,
+}
+
+impl PhiLshIndex {
+    /// Empty index; `quantum` must be resolved by the caller
+    pub fn new(config: &LshConfig, quantum: f64) -> Self {
+        let mut rng = StdRng::seed_from_u64(config.seed);
+        let tables = (0..config.tables.max(1))
+            .map(|_| LshTable {
+                positions: (0..config.positions.max(1)).map(|_| rng.gen()).collect(),
+                buckets: BTreeMap::new(),
+            })
+            .collect();
+        Self { config: Some(*config), quantum, tables, entries: BTreeMap::new() }
+    }
+
+    /// Whether the index was built by `new(config, quantum)`, so entries hash the same way
+    pub fn is_built_with(&self, config: &LshConfig, quantum: f64) -> bool {
+        self.config.as_ref() == Some(config) && self.quantum == quantum
+    }
+
+    pub fn quantum(&self) -> f64 {
+        self.quantum
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.entries.contains_key(name)
+    }
+
+    /// Indexed names in sorted order
+    pub fn names(&self) -> Vec<String> {
+        self.entries.keys().cloned().collect()
+    }
+
+    /// Whether `name` is indexed with exactly these values
+    pub fn is_current(&self, name: &str, data: &[f64]) -> bool {
+        self.entries.get(name).is_some_and(|e| e.checksum == phi_checksum(data))
+    }
+
+    fn keys(&self, data: &[f64]) -> Vec<u64> {
+        if data.is_empty() {
+            return Vec::new();
+        }
+        self.tables
+            .iter()
+            .map(|table| {
+                let mut hasher = Fnv1a64::new();
+                hasher.update(&(data.len() as u64).to_le_bytes());
+                for &p in &table.positions {
+                    let code = (data[p as usize % data.len()] / self.quantum).round() as i64;
+                    hasher.update(&code.to_le_bytes());
+                }
+                hasher.finish()
+            })
+            .collect()
+    }
+
+    /// Index `data` under `name`, replacing what was indexed before
+    pub fn insert(&mut self, name: &str, data: &[f64]) {
+        self.remove(name);
+        let keys = self.keys(data);
+        for (table, key) in self.tables.iter_mut().zip(&keys) {
+            table.buckets.entry(*key).or_default().push(name.to_string());
+        }
+        self.entries.insert(name.to_string(), LshEntry { checksum: phi_checksum(data), keys });
+    }
+
+    /// Drop `name`; returns whether it was indexed
+    pub fn remove(&mut self, name: &str) -> bool {
+        let Some(entry) = self.entries.remove(name) else {
+            return false;
+        };
+        for (table, key) in self.tables.iter_mut().zip(&entry.keys) {
+            if let Some(bucket) = table.buckets.get_mut(key) {
+                bucket.retain(|n| n != name);
+                if bucket.is_empty() {
+                    table.buckets.remove(key);
+                }
+            }
+        }
+        true
+    }
+
+    /// Names sharing a bucket with `input` in any table
+    pub fn candidates(&self, input: &[f64]) -> BTreeSet<String> {
+        let mut found = BTreeSet::new();
+        for (table, key) in self.tables.iter().zip(self.keys(input)) {
+            if let Some(bucket) = table.buckets.get(&key) {
+                found.extend(bucket.iter().cloned());
+            }
+        }
+        found
+    }
+
+    /// Persist next to a store's manifest
+    pub fn save<P: AsRef<Path>>(&self, base_path: P) -> PhiResult<()> {
+        let path = base_path.as_ref().join(LSH_INDEX_FILE);
+        let tmp = base_path.as_ref().join(format!("{}.tmp", LSH_INDEX_FILE));
+        fs::write(&tmp, serde_json::to_vec(self)?)?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    /// Index persisted in a store directory, if one was saved
+    pub fn load<P: AsRef<Path>>(base_path: P) -> PhiResult<Option<Self>> {
+        let path = base_path.as_ref().join(LSH_INDEX_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_slice(&fs::read(path)?)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lsh_candidates_and_updates() {
+        let mut index = PhiLshIndex::new(&LshConfig::default(), 0.01);
+        index.insert("alpha", &[0.10, 0.20, 0.30, 0.40]);
+        index.insert("beta", &[0.50, 0.60, 0.70, 0.80]);
+        index.insert("short", &[0.10, 0.20]);
+
+        assert_eq!(index.candidates(&[0.10, 0.20, 0.30, 0.40]), BTreeSet::from(["alpha".to_string()]));
+        assert!(index.candidates(&[]).is_empty());
+        assert!(index.is_current("beta", &[0.50, 0.60, 0.70, 0.80]));
+
+        index.insert("beta", &[0.10, 0.20, 0.30, 0.40]);
+        assert!(!index.is_current("beta", &[0.50, 0.60, 0.70, 0.80]));
+        assert_eq!(index.candidates(&[0.10, 0.20, 0.30, 0.40]).len(), 2);
+        assert!(index.remove("alpha"));
+        assert!(!index.remove("alpha"));
+        assert_eq!(index.candidates(&[0.10, 0.20, 0.30, 0.40]), BTreeSet::from(["beta".to_string()]));
+
+        let dir = ".phi_lsh_store";
+        fs::create_dir_all(dir).unwrap();
+        assert!(PhiLshIndex::load(dir).unwrap().is_none());
+        index.save(dir).unwrap();
+        let loaded = PhiLshIndex::load(dir).unwrap().unwrap();
+        assert_eq!(loaded.candidates(&[0.10, 0.20, 0.30, 0.40]), index.candidates(&[0.10, 0.20, 0.30, 0.40]));
+        assert_eq!((loaded.len(), loaded.quantum()), (2, 0.01));
+        assert!(loaded.is_built_with(&LshConfig::default(), 0.01));
+        assert!(!loaded.is_built_with(&LshConfig { tables: 4, ..LshConfig::default() }, 0.01));
+        assert!(!loaded.is_built_with(&LshConfig::default(), 0.1));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
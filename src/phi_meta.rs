@@ -1,57 +1,765 @@
 //! phi_meta: metadata handler for φ-memory entries
 //! Supports structured save/load of metadata alongside binary φ-data
 
-use std::fs::File;
-use std::io::{BufWriter, BufReader, Write, BufRead};
-use std::path::Path;
+use crate::phi_error::{PhiError, PhiResult};
+use crate::core::PHI_TABLE;
+use crate::phi_schema::{upgrade_metadata, META_SCHEMA_VERSION};
+use crate::phi_spec::{EncodingSpec, Quantization};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
 use chrono::{DateTime, Utc};
+use serde::de::IgnoredAny;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Sidecar file format for metadata
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MetaFormat {
+    /// Line-based `key=value` (`.meta.txt`)
+    #[default]
+    Text,
+    /// `.meta.json`
+    Json,
+    /// `.meta.toml`
+    Toml,
+}
+
+impl MetaFormat {
+    /// All formats, in the order `load` looks for them
+    pub const ALL: [MetaFormat; 3] = [MetaFormat::Json, MetaFormat::Toml, MetaFormat::Text];
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            MetaFormat::Text => "meta.txt",
+            MetaFormat::Json => "meta.json",
+            MetaFormat::Toml => "meta.toml",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "text" | "txt" => Some(MetaFormat::Text),
+            "json" => Some(MetaFormat::Json),
+            "toml" => Some(MetaFormat::Toml),
+            _ => None,
+        }
+    }
+
+    /// Sidecar path for an entry
+    pub fn path(self, name: &str, base_path: &Path) -> PathBuf {
+        base_path.join(format!("{}.{}", name, self.extension()))
+    }
+
+    /// Split a sidecar file name into entry name and format
+    pub fn strip(file_name: &str) -> Option<(&str, MetaFormat)> {
+        MetaFormat::ALL.into_iter().find_map(|format| {
+            file_name
+                .strip_suffix(format.extension())
+                .and_then(|rest| rest.strip_suffix('.'))
+                .map(|name| (name, format))
+        })
+    }
+}
+
+impl fmt::Display for MetaFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            MetaFormat::Text => "text",
+            MetaFormat::Json => "JSON",
+            MetaFormat::Toml => "TOML",
+        })
+    }
+}
+
+/// Keys every `.meta.txt` file must contain
+pub const REQUIRED_KEYS: &[&str] = &["n", "step", "length", "saved_at"];
+
+/// Top-level keys a JSON/TOML sidecar may contain
+const STRUCTURED_KEYS: &[&str] = &[
+    "schema_version", "n", "step", "length", "saved_at", "sample_rate", "unit", "description", "source",
+    "encoding", "quantization", "table_version", "crate_version", "route_threshold", "checksum", "tags",
+];
+
+/// Serialize checksums as hex strings: TOML integers cannot hold a full u64
+mod hex_checksum {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &Option<u64>, serializer: S) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(v) => serializer.serialize_str(&format!("{:016x}", v)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+        match Option::<String>::deserialize(deserializer)? {
+            Some(s) => u64::from_str_radix(&s, 16).map(Some).map_err(serde::de::Error::custom),
+            None => Ok(None),
+        }
+    }
+}
+
+fn line_of(text: &str, offset: usize) -> usize {
+    text[..offset.min(text.len())].matches('\n').count() + 1
+}
+
+fn syntax_error<E: fmt::Display>(format: MetaFormat, line: usize, err: E) -> PhiError {
+    PhiError::Syntax { format, line, message: err.to_string() }
+}
+
+/// Line on which a top-level key of a JSON/TOML sidecar is set, 0 if it cannot be found
+fn key_line(text: &str, format: MetaFormat, key: &str) -> usize {
+    let quoted = format!("\"{}\"", key);
+    let key = if format == MetaFormat::Json { quoted.as_str() } else { key };
+    text.lines()
+        .position(|line| {
+            line.trim_start()
+                .strip_prefix(key)
+                .is_some_and(|rest| rest.trim_start().starts_with([':', '=']))
+        })
+        .map_or(0, |index| index + 1)
+}
+
+/// Deserialize a JSON or TOML sidecar, with the checks the text parser applies
+fn parse_structured(text: &str, format: MetaFormat) -> PhiResult<PhiMetadata> {
+    let (meta, keys): (PhiMetadata, BTreeMap<String, IgnoredAny>) = match format {
+        MetaFormat::Json => {
+            let json = |e: serde_json::Error| syntax_error(format, e.line(), e);
+            (serde_json::from_str(text).map_err(json)?, serde_json::from_str(text).map_err(json)?)
+        }
+        MetaFormat::Toml => {
+            let toml = |e: toml::de::Error| {
+                let line = e.span().map_or(0, |span| line_of(text, span.start));
+                syntax_error(format, line, e.message())
+            };
+            (toml::from_str(text).map_err(toml)?, toml::from_str(text).map_err(toml)?)
+        }
+        MetaFormat::Text => {
+            let (meta, mut issues) = PhiMetadata::parse(text);
+            return match issues.is_empty() {
+                true => Ok(meta),
+                false => Err(issues.remove(0)),
+            };
+        }
+    };
+    // Records from a newer release are reported as such by the schema upgrade
+    if meta.schema_version > META_SCHEMA_VERSION {
+        return Ok(meta);
+    }
+
+    if let Some(key) = keys.keys().find(|key| !STRUCTURED_KEYS.contains(&key.as_str())) {
+        return Err(PhiError::UnknownKey { line: key_line(text, format, key), key: key.clone() });
+    }
+    let malformed = |key: &str, reason: String, value: String| PhiError::Malformed {
+        line: key_line(text, format, key),
+        key: key.to_string(),
+        reason: format!("{} (got '{}')", reason, value),
+    };
+    if !(1..=PHI_TABLE.len()).contains(&meta.n) {
+        return Err(malformed("n", format!("n must be in 1..={}", PHI_TABLE.len()), meta.n.to_string()));
+    }
+    if !(meta.step.is_finite() && meta.step >= 0.0) {
+        return Err(malformed("step", "not a finite, non-negative number".to_string(), meta.step.to_string()));
+    }
+    if let Some(rate) = meta.sample_rate.filter(|v| !(v.is_finite() && *v > 0.0)) {
+        return Err(malformed("sample_rate", "not a positive number".to_string(), rate.to_string()));
+    }
+    if let Some(threshold) = meta.route_threshold.filter(|v| !(0.0..=1.0).contains(v)) {
+        return Err(malformed("route_threshold", "not a number between 0 and 1".to_string(), threshold.to_string()));
+    }
+    Ok(meta)
+}
+
+fn read_meta_file(name: &str, base_path: &Path) -> PhiResult<(String, MetaFormat)> {
+    let (bytes, format) = read_meta_bytes(name, base_path)?;
+    let text = String::from_utf8(bytes).map_err(|e| PhiError::parse("metadata", e))?;
+    Ok((text, format))
+}
+
+/// Raw content and format of the sidecar stored for `name`
+pub(crate) fn read_meta_bytes(name: &str, base_path: &Path) -> PhiResult<(Vec<u8>, MetaFormat)> {
+    let format = PhiMetadata::detect_format(name, base_path).ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::NotFound, format!("no metadata for '{}'", name))
+    })?;
+    Ok((std::fs::read(format.path(name, base_path))?, format))
+}
+
+/// Write `content` as the `format` sidecar of `name`, replacing sidecars in other formats
+pub(crate) fn write_meta_bytes(name: &str, base_path: &Path, format: MetaFormat, content: &[u8]) -> PhiResult<()> {
+    std::fs::write(format.path(name, base_path), content)?;
+    for other in MetaFormat::ALL.iter().filter(|&&f| f != format) {
+        let path = other.path(name, base_path);
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+/// How the values of a φ-memory were produced
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PhiEncoding {
+    /// Values stored as given
+    Raw,
+    /// `hybrid_phi_approximate` output
+    Phi,
+    /// `phi_quantized_encode` output
+    PhiQuantized,
+}
+
+impl PhiEncoding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PhiEncoding::Raw => "raw",
+            PhiEncoding::Phi => "phi",
+            PhiEncoding::PhiQuantized => "phi-quantized",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "raw" => Some(PhiEncoding::Raw),
+            "phi" => Some(PhiEncoding::Phi),
+            "phi-quantized" => Some(PhiEncoding::PhiQuantized),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct PhiMetadata {
+    /// Layout version of the record; 0 until an unversioned record is upgraded
+    #[serde(default)]
+    pub schema_version: u32,
     pub n: usize,
     pub step: f64,
     pub length: usize,
     pub saved_at: DateTime<Utc>,
+    /// Samples per second of the original signal
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sample_rate: Option<f64>,
+    /// Physical unit of the original values
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Device or pipeline the data came from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<PhiEncoding>,
+    /// Quantization applied by the encoder (see `encoding_spec`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quantization: Option<Quantization>,
+    /// `PHI_TABLE_VERSION` the data was encoded with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub table_version: Option<u32>,
+    /// Crate version that wrote the data
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crate_version: Option<String>,
+    /// Minimum score for routing to this entry, overriding the router's threshold
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub route_threshold: Option<f64>,
+    /// `phi_checksum` of the stored values
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "hex_checksum")]
+    pub checksum: Option<u64>,
+    /// Free-form user labels
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: BTreeMap<String, String>,
+}
+
+/// Escape a value so it fits on one `key=value` line
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+/// Escape a tag key, which additionally must not contain a bare `=`
+fn escape_key(key: &str) -> String {
+    escape(key).replace('=', "\\=")
+}
+
+/// Split `key=value` at the first `=` that is not escaped
+fn split_tag(kv: &str) -> Option<(&str, &str)> {
+    let mut escaped = false;
+    for (i, c) in kv.char_indices() {
+        match c {
+            '\\' if !escaped => escaped = true,
+            '=' if !escaped => return Some((&kv[..i], &kv[i + 1..])),
+            _ => escaped = false,
+        }
+    }
+    None
+}
+
+fn unescape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('n') => out.push('\n'),
+                Some(other) => out.push(other),
+                None => out.push('\\'),
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
 }
 
 impl PhiMetadata {
+    /// Metadata with the required fields, stamped now
+    pub fn new(n: usize, step: f64, length: usize) -> Self {
+        Self {
+            schema_version: META_SCHEMA_VERSION,
+            n,
+            step,
+            length,
+            saved_at: Utc::now(),
+            sample_rate: None,
+            unit: None,
+            description: None,
+            source: None,
+            encoding: None,
+            quantization: None,
+            table_version: None,
+            crate_version: None,
+            route_threshold: None,
+            checksum: None,
+            tags: BTreeMap::new(),
+        }
+    }
+
+    /// Metadata recording a full encoding spec, stamped now
+    pub fn for_spec(spec: &EncodingSpec, length: usize) -> Self {
+        let mut meta = Self::new(spec.n, spec.step, length);
+        meta.set_encoding_spec(spec);
+        meta
+    }
+
+    /// Record how the stored values were produced
+    pub fn set_encoding_spec(&mut self, spec: &EncodingSpec) {
+        self.n = spec.n;
+        self.step = spec.step;
+        self.encoding = Some(spec.codec);
+        self.quantization = Some(spec.quantization);
+        self.table_version = Some(spec.table_version);
+        self.crate_version = Some(spec.crate_version.clone());
+    }
+
+    /// Encoding spec of the stored values, if one was recorded
+    ///
+    /// Records that name only the codec get the defaults of the first table version.
+    pub fn encoding_spec(&self) -> Option<EncodingSpec> {
+        let codec = self.encoding?;
+        let quantization = self.quantization.unwrap_or(match codec {
+            PhiEncoding::PhiQuantized => Quantization::Round,
+            _ => Quantization::None,
+        });
+        Some(EncodingSpec {
+            codec,
+            n: self.n,
+            step: self.step,
+            quantization,
+            table_version: self.table_version.unwrap_or(1),
+            crate_version: self.crate_version.clone().unwrap_or_default(),
+        })
+    }
+
+    /// Decode stored values using the recorded encoding spec
+    pub fn decode(&self, stored: &[f64]) -> PhiResult<Vec<f64>> {
+        let spec = self.encoding_spec().ok_or_else(|| PhiError::format("metadata records no encoding"))?;
+        spec.decode(stored).map_err(|reason| PhiError::format(format!("unsupported encoding: {}", reason)))
+    }
+
     /// Save metadata to .meta.txt file
-    pub fn save<P: AsRef<Path>>(&self, name: &str, base_path: P) -> std::io::Result<()> {
-        let path = base_path.as_ref().join(format!("{}.meta.txt", name));
-        let mut writer = BufWriter::new(File::create(path)?);
-        writeln!(writer, "n={}", self.n)?;
-        writeln!(writer, "step={:.6}", self.step)?;
-        writeln!(writer, "length={}", self.length)?;
-        writeln!(writer, "saved_at={}", self.saved_at.to_rfc3339())?;
+    pub fn save<P: AsRef<Path>>(&self, name: &str, base_path: P) -> PhiResult<()> {
+        self.save_as(name, base_path, MetaFormat::Text)
+    }
+
+    /// Save metadata as a sidecar in the given format, replacing sidecars in other formats
+    pub fn save_as<P: AsRef<Path>>(&self, name: &str, base_path: P, format: MetaFormat) -> PhiResult<()> {
+        write_meta_bytes(name, base_path.as_ref(), format, self.render(format)?.as_bytes())
+    }
+
+    /// Sidecar content in the given format
+    pub(crate) fn render(&self, format: MetaFormat) -> PhiResult<String> {
+        match format {
+            MetaFormat::Text => Ok(self.to_text()),
+            MetaFormat::Json => serde_json::to_string_pretty(self).map_err(|e| PhiError::format(e.to_string())),
+            MetaFormat::Toml => toml::to_string(self).map_err(|e| PhiError::format(e.to_string())),
+        }
+    }
+
+    /// Render metadata in the `key=value` text format
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        let mut line = |key: &str, value: String| {
+            out.push_str(key);
+            out.push('=');
+            out.push_str(&value);
+            out.push('\n');
+        };
+        line("schema_version", self.schema_version.to_string());
+        line("n", self.n.to_string());
+        line("step", self.step.to_string());
+        line("length", self.length.to_string());
+        line("saved_at", self.saved_at.to_rfc3339());
+        if let Some(rate) = self.sample_rate {
+            line("sample_rate", rate.to_string());
+        }
+        if let Some(unit) = &self.unit {
+            line("unit", escape(unit));
+        }
+        if let Some(description) = &self.description {
+            line("description", escape(description));
+        }
+        if let Some(source) = &self.source {
+            line("source", escape(source));
+        }
+        if let Some(encoding) = self.encoding {
+            line("encoding", encoding.as_str().to_string());
+        }
+        if let Some(quantization) = self.quantization {
+            line("quantization", quantization.as_str().to_string());
+        }
+        if let Some(version) = self.table_version {
+            line("table_version", version.to_string());
+        }
+        if let Some(version) = &self.crate_version {
+            line("crate_version", escape(version));
+        }
+        if let Some(threshold) = self.route_threshold {
+            line("route_threshold", threshold.to_string());
+        }
+        if let Some(checksum) = self.checksum {
+            line("checksum", format!("{:016x}", checksum));
+        }
+        for (key, value) in &self.tags {
+            line(&format!("tag.{}", escape_key(key)), escape(value));
+        }
+        out
+    }
+
+    /// Format of the sidecar stored for `name`, if any
+    pub fn detect_format<P: AsRef<Path>>(name: &str, base_path: P) -> Option<MetaFormat> {
+        MetaFormat::ALL
+            .into_iter()
+            .find(|format| format.path(name, base_path.as_ref()).exists())
+    }
+
+    /// Delete every sidecar stored for `name`; returns whether one existed
+    pub fn remove<P: AsRef<Path>>(name: &str, base_path: P) -> PhiResult<bool> {
+        let mut removed = false;
+        for format in MetaFormat::ALL {
+            let path = format.path(name, base_path.as_ref());
+            if path.exists() {
+                std::fs::remove_file(path)?;
+                removed = true;
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Load metadata from its sidecar, whichever format it is in (old four-line files included)
+    ///
+    /// Parsing is strict: the first missing, duplicate, unknown or malformed key fails with
+    /// the matching `PhiError` variant and its line number. Use `load_lenient` to read damaged files.
+    pub fn load<P: AsRef<Path>>(name: &str, base_path: P) -> PhiResult<Self> {
+        Self::load_versioned(name, base_path).map(|(meta, _)| meta)
+    }
+
+    /// Load and upgrade metadata strictly, also returning the schema version it was stored with
+    pub fn load_versioned<P: AsRef<Path>>(name: &str, base_path: P) -> PhiResult<(Self, u32)> {
+        let (text, format) = read_meta_file(name, base_path.as_ref())?;
+        Self::from_sidecar(&text, format)
+    }
+
+    /// Parse sidecar content strictly and upgrade it, also returning its stored schema version
+    pub(crate) fn from_sidecar(text: &str, format: MetaFormat) -> PhiResult<(Self, u32)> {
+        let mut meta = parse_structured(text, format)?;
+        let stored = upgrade_metadata(&mut meta)?;
+        Ok((meta, stored))
+    }
+
+    /// Load metadata, substituting defaults for bad values and reporting each problem
+    ///
+    /// Only the text format can be recovered line by line; JSON/TOML sidecars load strictly.
+    pub fn load_lenient<P: AsRef<Path>>(name: &str, base_path: P) -> PhiResult<(Self, Vec<PhiError>)> {
+        let (text, format) = read_meta_file(name, base_path.as_ref())?;
+        let (mut meta, mut issues) = match format {
+            MetaFormat::Text => Self::parse(&text),
+            _ => (parse_structured(&text, format)?, Vec::new()),
+        };
+        if let Err(e) = upgrade_metadata(&mut meta) {
+            issues.push(e);
+        }
+        Ok((meta, issues))
+    }
+
+    /// Parse `.meta.txt` content into metadata plus every issue found
+    ///
+    /// The record is returned as stored; `load*` additionally upgrade it to the current schema.
+    pub fn parse(text: &str) -> (Self, Vec<PhiError>) {
+        let mut meta = Self { schema_version: 0, ..Self::new(0, 0.0, 0) };
+        let mut issues = Vec::new();
+        let mut seen: BTreeMap<String, usize> = BTreeMap::new();
+
+        for (index, raw) in text.lines().enumerate() {
+            let line = index + 1;
+            if raw.trim().is_empty() {
+                continue;
+            }
+            let Some((key, value)) = split_tag(raw) else {
+                issues.push(PhiError::Malformed {
+                    line,
+                    key: raw.to_string(),
+                    reason: "expected key=value".to_string(),
+                });
+                continue;
+            };
+            let key_name = unescape(key);
+            if let Some(&first_line) = seen.get(&key_name) {
+                issues.push(PhiError::DuplicateKey { key: key_name.clone(), line, first_line });
+            }
+            seen.insert(key_name.clone(), line);
+
+            let malformed = |reason: &str| PhiError::Malformed {
+                line,
+                key: key_name.clone(),
+                reason: format!("{} (got '{}')", reason, value),
+            };
+            match key {
+                "schema_version" => match value.parse() {
+                    Ok(v) => meta.schema_version = v,
+                    Err(_) => issues.push(malformed("not an unsigned integer")),
+                },
+                "n" => match value.parse::<usize>() {
+                    Ok(v) if (1..=PHI_TABLE.len()).contains(&v) => meta.n = v,
+                    Ok(_) => issues.push(malformed(&format!("n must be in 1..={}", PHI_TABLE.len()))),
+                    Err(_) => issues.push(malformed("not an unsigned integer")),
+                },
+                "step" => match value.parse::<f64>() {
+                    Ok(v) if v.is_finite() && v >= 0.0 => meta.step = v,
+                    _ => issues.push(malformed("not a finite, non-negative number")),
+                },
+                "length" => match value.parse() {
+                    Ok(v) => meta.length = v,
+                    Err(_) => issues.push(malformed("not an unsigned integer")),
+                },
+                "saved_at" => match DateTime::parse_from_rfc3339(value) {
+                    Ok(dt) => meta.saved_at = dt.with_timezone(&Utc),
+                    Err(_) => issues.push(malformed("not an RFC 3339 timestamp")),
+                },
+                "sample_rate" => match value.parse::<f64>() {
+                    Ok(v) if v.is_finite() && v > 0.0 => meta.sample_rate = Some(v),
+                    _ => issues.push(malformed("not a positive number")),
+                },
+                "unit" => meta.unit = Some(unescape(value)),
+                "description" => meta.description = Some(unescape(value)),
+                "source" => meta.source = Some(unescape(value)),
+                "encoding" => match PhiEncoding::parse(value) {
+                    Some(v) => meta.encoding = Some(v),
+                    None => issues.push(malformed("unknown encoding")),
+                },
+                "quantization" => match Quantization::parse(value) {
+                    Some(v) => meta.quantization = Some(v),
+                    None => issues.push(malformed("unknown quantization")),
+                },
+                "table_version" => match value.parse() {
+                    Ok(v) => meta.table_version = Some(v),
+                    Err(_) => issues.push(malformed("not an unsigned integer")),
+                },
+                "crate_version" => meta.crate_version = Some(unescape(value)),
+                "route_threshold" => match value.parse::<f64>() {
+                    Ok(v) if (0.0..=1.0).contains(&v) => meta.route_threshold = Some(v),
+                    _ => issues.push(malformed("not a number between 0 and 1")),
+                },
+                "checksum" => match u64::from_str_radix(value, 16) {
+                    Ok(v) => meta.checksum = Some(v),
+                    Err(_) => issues.push(malformed("not a hexadecimal checksum")),
+                },
+                _ => match key_name.strip_prefix("tag.") {
+                    Some(tag) => {
+                        meta.tags.insert(tag.to_string(), unescape(value));
+                    }
+                    None => issues.push(PhiError::UnknownKey { line, key: key_name.clone() }),
+                },
+            }
+        }
+
+        for key in REQUIRED_KEYS {
+            if !seen.contains_key(*key) {
+                issues.push(PhiError::MissingKey { key });
+            }
+        }
+        (meta, issues)
+    }
+
+    /// Check the recorded length against the number of stored values
+    pub fn validate_length(&self, actual: usize) -> PhiResult<()> {
+        if self.length != actual {
+            return Err(PhiError::Validation(format!(
+                "metadata says length={} but {} values are stored",
+                self.length, actual
+            )));
+        }
         Ok(())
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_extended_fields_roundtrip() {
+        let dir = ".phi_meta_ext_store";
+        fs::create_dir_all(dir).unwrap();
+
+        let mut meta = PhiMetadata::new(10, 0.01, 128);
+        meta.sample_rate = Some(250.0);
+        meta.unit = Some("mV".to_string());
+        meta.description = Some("resting ECG\nlead II".to_string());
+        meta.source = Some("holter-7".to_string());
+        meta.encoding = Some(PhiEncoding::PhiQuantized);
+        meta.route_threshold = Some(0.95);
+        meta.checksum = Some(0xdead_beef);
+        meta.tags.insert("subject".to_string(), "42".to_string());
+        meta.tags.insert("label".to_string(), "a=b".to_string());
+        meta.tags.insert("x=y".to_string(), "z".to_string());
+        meta.save("ecg", dir).unwrap();
+
+        let loaded = PhiMetadata::load("ecg", dir).unwrap();
+        assert_eq!(loaded.description, meta.description);
+        assert_eq!(loaded.tags, meta.tags);
+        assert_eq!(loaded.encoding, Some(PhiEncoding::PhiQuantized));
+        assert_eq!(loaded.checksum, Some(0xdead_beef));
+        assert_eq!(loaded.sample_rate, Some(250.0));
+        assert_eq!(loaded.route_threshold, Some(0.95));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
 
-    /// Load metadata from .meta.txt file
-    pub fn load<P: AsRef<Path>>(name: &str, base_path: P) -> std::io::Result<Self> {
-        let path = base_path.as_ref().join(format!("{}.meta.txt", name));
-        let file = BufReader::new(File::open(path)?);
-
-        let mut n = 0;
-        let mut step = 0.0;
-        let mut length = 0;
-        let mut saved_at = Utc::now();
-
-        for line in file.lines() {
-            let line = line?;
-            if let Some(v) = line.strip_prefix("n=") {
-                n = v.parse().unwrap_or(0);
-            } else if let Some(v) = line.strip_prefix("step=") {
-                step = v.parse().unwrap_or(0.0);
-            } else if let Some(v) = line.strip_prefix("length=") {
-                length = v.parse().unwrap_or(0);
-            } else if let Some(v) = line.strip_prefix("saved_at=") {
-                saved_at = DateTime::parse_from_rfc3339(v)
-                    .map(|dt| dt.with_timezone(&Utc))
-                    .unwrap_or(Utc::now());
+    #[test]
+    fn test_strict_reports_line_numbers() {
+        let text = "n=10\nstep=abc\nlength=4\nn=11\nbogus\n";
+        let (_, issues) = PhiMetadata::parse(text);
+        assert!(matches!(&issues[0], PhiError::Malformed { line: 2, key, .. } if key == "step"));
+        assert!(matches!(&issues[1], PhiError::DuplicateKey { line: 4, first_line: 1, .. }));
+        assert!(matches!(&issues[2], PhiError::Malformed { line: 5, .. }));
+        assert!(matches!(&issues[3], PhiError::MissingKey { key: "saved_at" }));
+        assert_eq!(issues.len(), 4);
+
+        let (_, issues) = PhiMetadata::parse("n=0\nstep=0.01\nlength=1\nsaved_at=2025-05-05T00:00:00Z\n");
+        assert!(matches!(&issues[..], [PhiError::Malformed { line: 1, .. }]));
+    }
+
+    #[test]
+    fn test_strict_and_lenient_loading() {
+        let dir = ".phi_meta_strict_store";
+        fs::create_dir_all(dir).unwrap();
+        fs::write(format!("{}/bad.meta.txt", dir), "n=ten\nstep=0.01\nlength=4\nsaved_at=yesterday\n").unwrap();
+
+        let err = PhiMetadata::load("bad", dir).unwrap_err();
+        assert_eq!(err.to_string(), "line 1: bad 'n': not an unsigned integer (got 'ten')");
+        assert!(matches!(PhiMetadata::load("absent", dir), Err(PhiError::Io(_))));
+
+        let (meta, warnings) = PhiMetadata::load_lenient("bad", dir).unwrap();
+        assert_eq!(meta.length, 4);
+        assert_eq!(warnings.len(), 2);
+
+        assert!(meta.validate_length(4).is_ok());
+        assert!(matches!(
+            meta.validate_length(3),
+            Err(PhiError::Validation(message)) if message == "metadata says length=4 but 3 values are stored"
+        ));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_json_and_toml_sidecars() {
+        let dir = ".phi_meta_format_store";
+        fs::create_dir_all(dir).unwrap();
+        let mut meta = PhiMetadata::new(10, 0.01, 3);
+        meta.checksum = Some(u64::MAX);
+        meta.unit = Some("mV".to_string());
+        meta.tags.insert("kind".to_string(), "ramp".to_string());
+
+        for format in [MetaFormat::Json, MetaFormat::Toml, MetaFormat::Text] {
+            meta.save_as("wave", dir, format).unwrap();
+            assert_eq!(PhiMetadata::detect_format("wave", dir), Some(format));
+            let loaded = PhiMetadata::load("wave", dir).unwrap();
+            assert_eq!(loaded.checksum, meta.checksum);
+            assert_eq!(loaded.tags, meta.tags);
+            assert_eq!(loaded.unit, meta.unit);
+        }
+        // Only the latest format is kept
+        assert_eq!(fs::read_dir(dir).unwrap().count(), 1);
+
+        fs::write(format!("{}/bad.meta.json", dir), "{\n  \"n\": 10,\n  \"step\": \"x\"\n}").unwrap();
+        assert!(matches!(
+            PhiMetadata::load("bad", dir),
+            Err(PhiError::Syntax { format: MetaFormat::Json, line: 3, .. })
+        ));
+        let err = PhiMetadata::load("bad", dir).unwrap_err();
+        assert!(err.to_string().starts_with("line 3: invalid JSON metadata"), "{}", err);
+        assert_eq!(MetaFormat::strip("a.b.meta.toml"), Some(("a.b", MetaFormat::Toml)));
+
+        // Structured sidecars get the same value and key checks as text ones
+        let mut json = serde_json::to_string_pretty(&PhiMetadata::new(10, 0.01, 3)).unwrap();
+        for (from, to, key, line) in [
+            ("\"n\": 10", "\"n\": 40", "n", 3),
+            ("\"step\": 0.01", "\"step\": -1.0", "step", 4),
+            ("\"length\": 3", "\"length\": 3,\n  \"bogus\": 1", "bogus", 6),
+        ] {
+            json = json.replace(from, to);
+            fs::write(format!("{}/bad.meta.json", dir), &json).unwrap();
+            match PhiMetadata::load("bad", dir) {
+                Err(PhiError::Malformed { line: l, key: k, .. }) | Err(PhiError::UnknownKey { line: l, key: k }) => {
+                    assert_eq!((k.as_str(), l), (key, line));
+                }
+                other => panic!("expected a problem with '{}', got {:?}", key, other),
             }
+            json = json.replace(to, from);
         }
+        fs::remove_file(format!("{}/bad.meta.json", dir)).unwrap();
+        let mut meta = PhiMetadata::new(10, 0.01, 3);
+        meta.route_threshold = Some(1.5);
+        meta.save_as("wave", dir, MetaFormat::Toml).unwrap();
+        assert!(matches!(
+            PhiMetadata::load("wave", dir),
+            Err(PhiError::Malformed { key, .. }) if key == "route_threshold"
+        ));
 
-        Ok(Self { n, step, length, saved_at })
+        fs::remove_dir_all(dir).unwrap();
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_reads_four_line_files() {
+        let dir = ".phi_meta_legacy_store";
+        fs::create_dir_all(dir).unwrap();
+        fs::write(
+            format!("{}/old.meta.txt", dir),
+            "n=10\nstep=0.010000\nlength=4\nsaved_at=2025-05-05T00:02:30+00:00\n",
+        )
+        .unwrap();
+
+        let loaded = PhiMetadata::load("old", dir).unwrap();
+        assert_eq!(loaded.n, 10);
+        assert_eq!(loaded.length, 4);
+        assert!(loaded.unit.is_none() && loaded.tags.is_empty());
+
+        // Bundles written before the extra fields still deserialize
+        let json = r#"{"n":10,"step":0.01,"length":4,"saved_at":"2025-05-05T00:02:30Z"}"#;
+        let parsed: PhiMetadata = serde_json::from_str(json).unwrap();
+        assert_eq!(parsed.encoding, None);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,431 @@
+//! phi_metric: similarity measures between φ-sequences for routing
+//! Every metric scores in `0.0..=1.0`, with 1 meaning identical
+
+/// Tolerance used when no quantization step is known
+pub const EXACT_TOLERANCE: f64 = 1e-6;
+
+/// Similarity between an input and a stored φ-sequence
+///
+/// `step` is the quantization step of the stored entry when its metadata records one.
+/// Point-wise built-ins score 0 when the lengths differ or either side is empty; `Dtw` and
+/// `SlidingWindow` compare sequences of any length.
+pub trait SimilarityMetric: Send + Sync {
+    /// Short name, as accepted by `metric_from_name`
+    fn name(&self) -> &str;
+
+    fn similarity(&self, input: &[f64], stored: &[f64], step: Option<f64>) -> f64;
+
+    /// Largest deviation counted as a match at one position, for metrics that score by
+    /// counting matching positions; `None` for metrics that score the sequences as a whole
+    fn tolerance(&self, _step: Option<f64>) -> Option<f64> {
+        None
+    }
+
+    /// Whether sequences of different lengths, or shifted against each other, can score
+    /// above 0
+    fn length_tolerant(&self) -> bool {
+        false
+    }
+
+    /// Index of the stored sample the score aligned with `input[0]` (negative when the input
+    /// is longer and the stored sequence starts inside it); `None` for metrics that warp
+    /// rather than shift
+    fn alignment(&self, _input: &[f64], _stored: &[f64], _step: Option<f64>) -> Option<isize> {
+        Some(0)
+    }
+
+    /// Whether LSH buckets of equal quantized codes can narrow the search; metrics whose
+    /// matches may fall in other buckets (scale- or shift-invariant, distance-based or
+    /// length-tolerant ones) keep scanning every entry
+    fn lsh_compatible(&self) -> bool {
+        false
+    }
+}
+
+fn comparable(a: &[f64], b: &[f64]) -> bool {
+    a.len() == b.len() && !a.is_empty()
+}
+
+/// Fraction of positions where the values differ by at most a tolerance
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Tolerance {
+    /// Fixed tolerance; `None` uses the stored entry's step, or `EXACT_TOLERANCE`
+    pub tolerance: Option<f64>,
+}
+
+impl Tolerance {
+    pub fn new(tolerance: f64) -> Self {
+        Self { tolerance: Some(tolerance) }
+    }
+}
+
+impl SimilarityMetric for Tolerance {
+    fn name(&self) -> &str {
+        "tolerance"
+    }
+
+    fn similarity(&self, input: &[f64], stored: &[f64], step: Option<f64>) -> f64 {
+        if !comparable(input, stored) {
+            return 0.0;
+        }
+        // A hair of slack so values exactly one step apart still count
+        let tolerance = self.tolerance.or(step).unwrap_or(EXACT_TOLERANCE) * (1.0 + 1e-9);
+        let matched = input.iter().zip(stored).filter(|(x, y)| (*x - *y).abs() <= tolerance).count();
+        matched as f64 / input.len() as f64
+    }
+
+    fn tolerance(&self, step: Option<f64>) -> Option<f64> {
+        Some(self.tolerance.or(step).unwrap_or(EXACT_TOLERANCE))
+    }
+
+    fn lsh_compatible(&self) -> bool {
+        true
+    }
+}
+
+/// Fraction of positions with the same quantization index `round(value / step)`
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Hamming {
+    /// Fixed step; `None` uses the stored entry's step, or `EXACT_TOLERANCE`
+    pub step: Option<f64>,
+}
+
+impl SimilarityMetric for Hamming {
+    fn name(&self) -> &str {
+        "hamming"
+    }
+
+    fn similarity(&self, input: &[f64], stored: &[f64], step: Option<f64>) -> f64 {
+        if !comparable(input, stored) {
+            return 0.0;
+        }
+        let step = self.step.or(step).filter(|s| *s > 0.0).unwrap_or(EXACT_TOLERANCE);
+        let index = |v: f64| (v / step).round() as i64;
+        let matched = input.iter().zip(stored).filter(|(x, y)| index(**x) == index(**y)).count();
+        matched as f64 / input.len() as f64
+    }
+
+    fn lsh_compatible(&self) -> bool {
+        true
+    }
+}
+
+/// Cosine of the angle between the sequences; opposite directions score 0
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Cosine;
+
+impl SimilarityMetric for Cosine {
+    fn name(&self) -> &str {
+        "cosine"
+    }
+
+    fn similarity(&self, input: &[f64], stored: &[f64], _step: Option<f64>) -> f64 {
+        if !comparable(input, stored) {
+            return 0.0;
+        }
+        let dot: f64 = input.iter().zip(stored).map(|(x, y)| x * y).sum();
+        let norms = norm(input) * norm(stored);
+        match norms == 0.0 {
+            true => same_or_zero(input, stored),
+            false => (dot / norms).clamp(0.0, 1.0),
+        }
+    }
+}
+
+/// Pearson correlation of the sequences; anti-correlation scores 0
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Pearson;
+
+impl SimilarityMetric for Pearson {
+    fn name(&self) -> &str {
+        "pearson"
+    }
+
+    fn similarity(&self, input: &[f64], stored: &[f64], _step: Option<f64>) -> f64 {
+        if !comparable(input, stored) {
+            return 0.0;
+        }
+        let centred = |v: &[f64]| {
+            let mean = v.iter().sum::<f64>() / v.len() as f64;
+            v.iter().map(|x| x - mean).collect::<Vec<_>>()
+        };
+        let (a, b) = (centred(input), centred(stored));
+        let spread = norm(&a) * norm(&b);
+        // Correlation is undefined for constant sequences
+        match spread == 0.0 {
+            true => same_or_zero(input, stored),
+            false => (a.iter().zip(&b).map(|(x, y)| x * y).sum::<f64>() / spread).clamp(0.0, 1.0),
+        }
+    }
+}
+
+/// `1 - |a - b| / (|a| + |b|)` with the Euclidean norm
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Euclidean;
+
+impl SimilarityMetric for Euclidean {
+    fn name(&self) -> &str {
+        "euclidean"
+    }
+
+    fn similarity(&self, input: &[f64], stored: &[f64], _step: Option<f64>) -> f64 {
+        if !comparable(input, stored) {
+            return 0.0;
+        }
+        let diff: Vec<f64> = input.iter().zip(stored).map(|(x, y)| x - y).collect();
+        normalised_distance(norm(&diff), norm(input) + norm(stored))
+    }
+}
+
+/// `1 - Σ|a - b| / (Σ|a| + Σ|b|)`
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct L1;
+
+impl SimilarityMetric for L1 {
+    fn name(&self) -> &str {
+        "l1"
+    }
+
+    fn similarity(&self, input: &[f64], stored: &[f64], _step: Option<f64>) -> f64 {
+        if !comparable(input, stored) {
+            return 0.0;
+        }
+        let l1 = |v: &[f64]| v.iter().map(|x| x.abs()).sum::<f64>();
+        let distance: f64 = input.iter().zip(stored).map(|(x, y)| (x - y).abs()).sum();
+        normalised_distance(distance, l1(input) + l1(stored))
+    }
+}
+
+/// Dynamic time warping, for sequences that are stretched, shifted or of different lengths
+///
+/// Scores `1 - D / (Σ|a| + Σ|b|)`, where `D` is the summed absolute difference along the
+/// cheapest warping path.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Dtw {
+    /// Sakoe–Chiba band: how far (in samples) the path may leave the diagonal;
+    /// widened to the length difference so a path always exists. `None` is unconstrained.
+    pub band: Option<usize>,
+}
+
+impl Dtw {
+    pub fn with_band(band: usize) -> Self {
+        Self { band: Some(band) }
+    }
+}
+
+impl SimilarityMetric for Dtw {
+    fn name(&self) -> &str {
+        "dtw"
+    }
+
+    fn similarity(&self, input: &[f64], stored: &[f64], _step: Option<f64>) -> f64 {
+        if input.is_empty() || stored.is_empty() {
+            return 0.0;
+        }
+        let l1 = |v: &[f64]| v.iter().map(|x| x.abs()).sum::<f64>();
+        normalised_distance(dtw_distance(input, stored, self.band), l1(input) + l1(stored))
+    }
+
+    fn length_tolerant(&self) -> bool {
+        true
+    }
+
+    fn alignment(&self, _input: &[f64], _stored: &[f64], _step: Option<f64>) -> Option<isize> {
+        None
+    }
+}
+
+/// Cost of the cheapest warping path between `a` and `b`, within `band` of the diagonal
+///
+/// Uses two rows of `b.len() + 1` cells; infinite if either side is empty.
+pub fn dtw_distance(a: &[f64], b: &[f64], band: Option<usize>) -> f64 {
+    if a.is_empty() || b.is_empty() {
+        return f64::INFINITY;
+    }
+    let band = band.map_or(usize::MAX, |w| w.max(a.len().abs_diff(b.len())));
+    let mut prev = vec![f64::INFINITY; b.len() + 1];
+    let mut cur = vec![f64::INFINITY; b.len() + 1];
+    prev[0] = 0.0;
+    for i in 1..=a.len() {
+        cur.fill(f64::INFINITY);
+        let lo = i.saturating_sub(band).max(1);
+        let hi = i.saturating_add(band).min(b.len());
+        for j in lo..=hi {
+            let cost = (a[i - 1] - b[j - 1]).abs();
+            cur[j] = cost + prev[j].min(prev[j - 1]).min(cur[j - 1]);
+        }
+        std::mem::swap(&mut prev, &mut cur);
+    }
+    prev[b.len()]
+}
+
+/// Best alignment of the shorter sequence inside the longer one
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WindowMatch {
+    /// Index of the stored sample aligned with `input[0]`; negative when the input is
+    /// longer and the stored sequence starts inside it
+    pub offset: isize,
+    pub score: f64,
+}
+
+/// Slide the shorter of `input` and `stored` along the longer and score each window with `metric`
+///
+/// Ties keep the smallest offset. `None` if either side is empty.
+pub fn best_window(
+    input: &[f64],
+    stored: &[f64],
+    step: Option<f64>,
+    metric: &dyn SimilarityMetric,
+) -> Option<WindowMatch> {
+    if input.is_empty() || stored.is_empty() {
+        return None;
+    }
+    let (short, long, sign) = match input.len() <= stored.len() {
+        true => (input, stored, 1),
+        false => (stored, input, -1),
+    };
+    let mut best: Option<WindowMatch> = None;
+    for (start, window) in long.windows(short.len()).enumerate() {
+        let score = match sign {
+            1 => metric.similarity(short, window, step),
+            _ => metric.similarity(window, short, step),
+        };
+        if best.is_none_or(|b| score > b.score) {
+            best = Some(WindowMatch { offset: sign * start as isize, score });
+        }
+    }
+    best
+}
+
+/// Scores an entry by its best-matching window (see `best_window`), so shorter or longer
+/// inputs can match part of a stored sequence
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SlidingWindow<M> {
+    pub inner: M,
+}
+
+impl<M: SimilarityMetric> SlidingWindow<M> {
+    pub fn new(inner: M) -> Self {
+        Self { inner }
+    }
+}
+
+impl<M: SimilarityMetric> SimilarityMetric for SlidingWindow<M> {
+    fn name(&self) -> &str {
+        "sliding"
+    }
+
+    fn similarity(&self, input: &[f64], stored: &[f64], step: Option<f64>) -> f64 {
+        best_window(input, stored, step, &self.inner).map_or(0.0, |m| m.score)
+    }
+
+    fn tolerance(&self, step: Option<f64>) -> Option<f64> {
+        self.inner.tolerance(step)
+    }
+
+    fn length_tolerant(&self) -> bool {
+        true
+    }
+
+    fn alignment(&self, input: &[f64], stored: &[f64], step: Option<f64>) -> Option<isize> {
+        best_window(input, stored, step, &self.inner).map(|m| m.offset)
+    }
+}
+
+fn norm(v: &[f64]) -> f64 {
+    v.iter().map(|x| x * x).sum::<f64>().sqrt()
+}
+
+fn normalised_distance(distance: f64, scale: f64) -> f64 {
+    match scale == 0.0 {
+        true => 1.0,
+        false => (1.0 - distance / scale).clamp(0.0, 1.0),
+    }
+}
+
+/// Score for degenerate inputs: 1 if identical, 0 otherwise
+fn same_or_zero(a: &[f64], b: &[f64]) -> f64 {
+    if a == b { 1.0 } else { 0.0 }
+}
+
+/// Built-in metric by name: `tolerance`, `hamming`, `cosine`, `pearson`, `euclidean`, `l1`,
+/// `dtw` (unconstrained) or `sliding` (tolerance over the best window)
+pub fn metric_from_name(name: &str) -> Option<Box<dyn SimilarityMetric>> {
+    match name {
+        "tolerance" => Some(Box::new(Tolerance::default())),
+        "hamming" => Some(Box::new(Hamming::default())),
+        "cosine" => Some(Box::new(Cosine)),
+        "pearson" => Some(Box::new(Pearson)),
+        "euclidean" => Some(Box::new(Euclidean)),
+        "l1" => Some(Box::new(L1)),
+        "dtw" => Some(Box::new(Dtw::default())),
+        "sliding" => Some(Box::new(SlidingWindow::new(Tolerance::default()))),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tolerance_and_hamming_use_step() {
+        let stored = [0.10, 0.20, 0.30, 0.40];
+        let one_step_off = [0.10, 0.21, 0.30, 0.40];
+        assert_eq!(Tolerance::default().similarity(&one_step_off, &stored, None), 0.75);
+        assert_eq!(Tolerance::default().similarity(&one_step_off, &stored, Some(0.01)), 1.0);
+        assert_eq!(Tolerance::new(0.001).similarity(&one_step_off, &stored, Some(0.01)), 0.75);
+        assert_eq!(Hamming::default().similarity(&one_step_off, &stored, Some(0.01)), 0.75);
+        assert_eq!(Hamming { step: Some(0.1) }.similarity(&one_step_off, &stored, None), 1.0);
+        assert_eq!(Tolerance::default().similarity(&[1.0], &stored, Some(0.01)), 0.0);
+    }
+
+    #[test]
+    fn test_shape_and_distance_metrics() {
+        let a = [1.0, 2.0, 3.0, 4.0];
+        let scaled = [2.0, 4.0, 6.0, 8.0];
+        let reversed = [4.0, 3.0, 2.0, 1.0];
+        for metric in ["cosine", "pearson", "euclidean", "l1", "tolerance", "hamming"] {
+            let metric = metric_from_name(metric).unwrap();
+            assert!((metric.similarity(&a, &a, Some(0.01)) - 1.0).abs() < 1e-12, "{}", metric.name());
+            let score = metric.similarity(&a, &reversed, Some(0.01));
+            assert!((0.0..1.0).contains(&score), "{}: {}", metric.name(), score);
+        }
+        assert!((Cosine.similarity(&a, &scaled, None) - 1.0).abs() < 1e-12);
+        assert!((Pearson.similarity(&a, &scaled, None) - 1.0).abs() < 1e-12);
+        assert_eq!(Pearson.similarity(&a, &reversed, None), 0.0);
+        assert!((Euclidean.similarity(&a, &scaled, None) - (1.0 - 1.0 / 3.0)).abs() < 1e-12);
+        assert!((L1.similarity(&a, &scaled, None) - (1.0 - 1.0 / 3.0)).abs() < 1e-12);
+        assert_eq!(Cosine.similarity(&[0.0, 0.0], &[0.0, 0.0], None), 1.0);
+        assert!(metric_from_name("manhattan").is_none());
+    }
+
+    #[test]
+    fn test_dtw_matches_stretched_sequences_within_band() {
+        let query = [1.0, 2.0, 3.0, 4.0];
+        let stretched: Vec<f64> = (0..128).map(|i| 1.0 + (i / 32) as f64).collect();
+        assert_eq!(dtw_distance(&query, &stretched, None), 0.0);
+        assert_eq!(Dtw::default().similarity(&query, &stretched, None), 1.0);
+        assert_eq!(Tolerance::default().similarity(&query, &stretched, None), 0.0);
+
+        let shifted = [0.0, 0.0, 1.0, 2.0, 3.0, 4.0, 4.0, 4.0];
+        let padded = [0.0, 1.0, 2.0, 3.0, 4.0, 4.0, 4.0, 4.0];
+        assert_eq!(dtw_distance(&shifted, &padded, Some(1)), 0.0);
+        assert!(dtw_distance(&shifted, &padded, Some(0)) > 0.0);
+        assert_eq!(dtw_distance(&[], &padded, None), f64::INFINITY);
+    }
+
+    #[test]
+    fn test_best_window_reports_offset() {
+        let stored: Vec<f64> = (0..128).map(|i| (i % 16) as f64).collect();
+        let query = [5.0, 6.0, 7.0, 8.0];
+        let found = best_window(&query, &stored, None, &Tolerance::default()).unwrap();
+        assert_eq!(found, WindowMatch { offset: 5, score: 1.0 });
+
+        let longer = [9.0, 9.0, 1.0, 2.0, 9.0];
+        let found = best_window(&longer, &[1.0, 2.0], None, &Tolerance::default()).unwrap();
+        assert_eq!(found, WindowMatch { offset: -2, score: 1.0 });
+        assert_eq!(SlidingWindow::new(Tolerance::default()).similarity(&query, &stored, None), 1.0);
+        assert!(best_window(&[], &stored, None, &Cosine).is_none());
+    }
+}
@@ -0,0 +1,301 @@
+//! phi_retention: TTL, retention policies and garbage collection for φ-stores
+//! Keeps long-running recorders from filling a store indefinitely
+
+use crate::phi_error::{PhiError, PhiResult};
+use crate::phi_fs::PhiMemoryStore;
+use crate::phi_dedup::OBJECTS_DIR;
+use crate::phi_meta::{MetaFormat, PhiMetadata};
+use chrono::{DateTime, Duration, Utc};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// How long a metadata sidecar may wait for its data before `gc` treats it as orphaned,
+/// since metadata may be saved before the data it describes
+pub const ORPHAN_GRACE_SECS: i64 = 60 * 60;
+
+/// Store-wide limits enforced by `PhiMemoryStore::gc`
+#[derive(Debug, Clone, Default)]
+pub struct RetentionPolicy {
+    /// Keep at most this many entries (oldest are evicted first)
+    pub max_entries: Option<usize>,
+    /// Keep total `.bin` size under this many bytes
+    pub max_bytes: Option<u64>,
+    /// Evict entries whose `saved_at` is older than this
+    pub max_age: Option<Duration>,
+}
+
+/// Summary of what a `gc` pass removed
+#[derive(Debug, Clone, Default)]
+pub struct GcReport {
+    pub expired: Vec<String>,
+    pub evicted: Vec<String>,
+    pub orphans_removed: Vec<PathBuf>,
+    /// Size of every file removed: data, metadata, history and unreferenced objects
+    pub bytes_freed: u64,
+}
+
+/// Total size of the files under `dir`
+fn disk_usage(dir: &Path) -> PhiResult<u64> {
+    let mut total = 0;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            total += disk_usage(&entry.path())?;
+        } else if file_type.is_file() {
+            total += entry.metadata()?.len();
+        }
+    }
+    Ok(total)
+}
+
+/// Size of a file, 0 if there is none
+fn file_size(path: &Path) -> u64 {
+    fs::metadata(path).ok().filter(|m| m.is_file()).map_or(0, |m| m.len())
+}
+
+/// When a sidecar was last written: the later of its recorded `saved_at` and its file time
+fn written_at(path: &Path, meta: Option<&PhiMetadata>) -> Option<DateTime<Utc>> {
+    let modified = fs::metadata(path).and_then(|m| m.modified()).ok().map(DateTime::<Utc>::from);
+    modified.max(meta.map(|m| m.saved_at))
+}
+
+struct Candidate {
+    name: String,
+    saved_at: DateTime<Utc>,
+    expires_at: Option<DateTime<Utc>>,
+    size: u64,
+}
+
+impl PhiMemoryStore {
+    /// Size of the files `delete` removes for an entry: data, metadata, history, and the
+    /// shared object when the entry holds its last reference
+    fn entry_usage(&self, name: &str) -> PhiResult<u64> {
+        let mut paths = vec![self.base_path().join(format!("{}.bin", name)), self.ref_path(name)];
+        if let Some(hash) = self.read_ref(name)? {
+            if self.refcounts()?.get(&hash).copied().unwrap_or(0) <= 1 {
+                paths.push(self.object_path(&hash));
+            }
+        }
+        paths.extend(MetaFormat::ALL.map(|format| format.path(name, self.base_path())));
+        let mut total: u64 = paths.iter().map(|p| file_size(p)).sum();
+        let history = self.versions_dir(name);
+        if history.is_dir() {
+            total += disk_usage(&history)?;
+        }
+        Ok(total)
+    }
+
+    /// `delete` an entry, counting what it frees
+    fn gc_delete(&self, name: &str, report: &mut GcReport) -> PhiResult<()> {
+        let size = self.entry_usage(name)?;
+        self.delete(name)?;
+        report.bytes_freed += size;
+        Ok(())
+    }
+
+    /// Expire a named entry `ttl` from now
+    pub fn set_ttl(&self, name: &str, ttl: Duration) -> PhiResult<()> {
+        let mut manifest = self.manifest()?;
+        let entry = manifest
+            .entries
+            .get_mut(name)
+            .ok_or_else(|| PhiError::NotFound(name.to_string()))?;
+        entry.expires_at = Some(Utc::now() + ttl);
+        manifest.save(self.base_path())
+    }
+
+    /// Remove expired entries, enforce the retention policy and clean up orphaned files
+    ///
+    /// Orphans are metadata sidecars without data written more than `ORPHAN_GRACE_SECS` ago,
+    /// and data with neither metadata nor a manifest record, such as files left behind by an
+    /// interrupted write. Entries saved without metadata are kept. For the size limit,
+    /// content-addressed entries count their shared object in full.
+    pub fn gc(&self, policy: &RetentionPolicy) -> PhiResult<GcReport> {
+        let mut report = GcReport::default();
+        let now = Utc::now();
+        let grace = Duration::seconds(ORPHAN_GRACE_SECS);
+
+        let mut bins = BTreeSet::new();
+        let mut metas = BTreeSet::new();
+        for entry in fs::read_dir(self.base_path())? {
+            let path = entry?.path();
+            if !path.is_file() {
+                continue;
+            }
+            let file_name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+            if let Some((name, _)) = MetaFormat::strip(&file_name) {
+                metas.insert(name.to_string());
+            } else if let Some(name) = file_name.strip_suffix(".bin").or(file_name.strip_suffix(".ref")) {
+                bins.insert(name.to_string());
+            }
+        }
+        let mut manifest = self.manifest()?;
+        for name in metas.difference(&bins) {
+            let meta = self.read_meta(name).ok();
+            for format in MetaFormat::ALL {
+                let path = format.path(name, self.base_path());
+                let waiting = written_at(&path, meta.as_ref()).is_some_and(|t| now - t < grace);
+                if path.exists() && !waiting {
+                    report.bytes_freed += file_size(&path);
+                    fs::remove_file(&path)?;
+                    report.orphans_removed.push(path);
+                }
+            }
+        }
+        for name in bins.difference(&metas) {
+            if manifest.get(name).is_some() {
+                continue;
+            }
+            let path = self.data_path(name)?;
+            self.gc_delete(name, &mut report)?;
+            report.orphans_removed.push(path);
+        }
+        bins.retain(|name| metas.contains(name) || manifest.get(name).is_some());
+
+        manifest.entries.retain(|name, _| bins.contains(name));
+        manifest.save(self.base_path())?;
+
+        let mut candidates = Vec::new();
+        for name in bins {
+            let size = fs::metadata(self.data_path(&name)?)?.len();
+            let record = manifest.get(&name);
+            let saved_at = self
+                .read_meta(&name)
+                .map(|m| m.saved_at)
+                .ok()
+                .or(record.map(|e| e.updated_at))
+                .unwrap_or(now);
+            candidates.push(Candidate {
+                name,
+                saved_at,
+                expires_at: record.and_then(|e| e.expires_at),
+                size,
+            });
+        }
+
+        // Per-entry TTL and maximum age
+        let mut kept = Vec::new();
+        for c in candidates {
+            let expired = c.expires_at.is_some_and(|t| t <= now);
+            let too_old = policy.max_age.is_some_and(|age| now - c.saved_at > age);
+            if expired || too_old {
+                self.gc_delete(&c.name, &mut report)?;
+                if expired {
+                    report.expired.push(c.name);
+                } else {
+                    report.evicted.push(c.name);
+                }
+            } else {
+                kept.push(c);
+            }
+        }
+
+        // Count and size limits, evicting the oldest entries first
+        kept.sort_by(|a, b| a.saved_at.cmp(&b.saved_at).then_with(|| a.name.cmp(&b.name)));
+        let mut total: u64 = kept.iter().map(|c| c.size).sum();
+        let mut remaining = kept.len();
+        for c in kept {
+            let over_count = policy.max_entries.is_some_and(|max| remaining > max);
+            let over_bytes = policy.max_bytes.is_some_and(|max| total > max);
+            if !over_count && !over_bytes {
+                break;
+            }
+            self.gc_delete(&c.name, &mut report)?;
+            total -= c.size;
+            remaining -= 1;
+            report.evicted.push(c.name);
+        }
+
+        let objects_dir = self.base_path().join(OBJECTS_DIR);
+        let mut object_sizes = BTreeMap::new();
+        if objects_dir.is_dir() {
+            for entry in fs::read_dir(&objects_dir)? {
+                let path = entry?.path();
+                object_sizes.insert(path.clone(), file_size(&path));
+            }
+        }
+        for path in self.collect_objects()? {
+            report.bytes_freed += object_sizes.get(&path).copied().unwrap_or(0);
+            report.orphans_removed.push(path);
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::phi_meta::PhiMetadata;
+
+    fn meta_at(length: usize, saved_at: DateTime<Utc>) -> PhiMetadata {
+        PhiMetadata { saved_at, ..PhiMetadata::new(10, 0.01, length) }
+    }
+
+    #[test]
+    fn test_gc_ttl_age_and_limits() {
+        let store = PhiMemoryStore::new(".phi_gc_store");
+        let now = Utc::now();
+        for (i, name) in ["old", "mid", "new", "ttl"].iter().enumerate() {
+            store.save(name, &[1.0, 2.0]).unwrap();
+            let saved_at = now - Duration::days(30 - 10 * i as i64);
+            store.save_meta(name, &meta_at(2, saved_at)).unwrap();
+        }
+        store.set_ttl("ttl", Duration::seconds(-1)).unwrap();
+        let freed: u64 = ["old", "mid", "ttl"]
+            .iter()
+            .flat_map(|name| [format!("{}.bin", name), format!("{}.meta.txt", name)])
+            .map(|file| fs::metadata(store.base_path().join(file)).unwrap().len())
+            .sum();
+
+        let policy = RetentionPolicy {
+            max_age: Some(Duration::days(25)),
+            max_entries: Some(1),
+            ..Default::default()
+        };
+        let report = store.gc(&policy).unwrap();
+
+        assert_eq!(report.expired, vec!["ttl"]);
+        assert_eq!(report.evicted, vec!["old", "mid"]);
+        assert_eq!(report.bytes_freed, freed);
+        assert_eq!(store.list().unwrap(), vec!["new"]);
+
+        fs::remove_dir_all(".phi_gc_store").unwrap();
+    }
+
+    #[test]
+    fn test_gc_removes_orphans() {
+        let store = PhiMemoryStore::new(".phi_gc_orphan_store");
+        store.save("data_only", &[1.0]).unwrap();
+        store.save("kept", &[1.0]).unwrap();
+        store.save_meta("kept", &meta_at(1, Utc::now())).unwrap();
+        let long_ago = Utc::now() - Duration::days(2);
+        meta_at(1, long_ago).save("meta_only", store.base_path()).unwrap();
+        fs::File::options()
+            .write(true)
+            .open(store.base_path().join("meta_only.meta.txt"))
+            .unwrap()
+            .set_modified(long_ago.into())
+            .unwrap();
+        // Metadata may be saved before its data, so a fresh sidecar is given time
+        store.save_meta("pending", &meta_at(1, Utc::now())).unwrap();
+        fs::copy(store.base_path().join("kept.bin"), store.base_path().join("stray.bin")).unwrap();
+
+        let stray = fs::metadata(store.base_path().join("stray.bin")).unwrap().len();
+        let meta_only = fs::metadata(store.base_path().join("meta_only.meta.txt")).unwrap().len();
+
+        // Data saved without metadata is a regular entry; only unrecorded data is orphaned
+        let report = store.gc(&RetentionPolicy::default()).unwrap();
+        assert_eq!(report.orphans_removed.len(), 2);
+        assert_eq!(report.bytes_freed, stray + meta_only);
+        assert_eq!(store.list().unwrap(), vec!["data_only", "kept"]);
+        assert_eq!(store.manifest().unwrap().names(), vec!["data_only", "kept"]);
+        assert_eq!(store.load("data_only").unwrap(), vec![1.0]);
+
+        store.save("pending", &[2.0]).unwrap();
+        assert_eq!(store.load_meta("pending").unwrap().length, 1);
+
+        fs::remove_dir_all(".phi_gc_orphan_store").unwrap();
+    }
+}
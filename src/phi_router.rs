@@ -1,55 +1,730 @@
 //! φ-router: signal-to-memory matching and routing
 //! Maps input φ-signals to named memory slots
 
+use crate::phi_error::PhiResult;
 use crate::phi_fs::PhiMemoryStore;
+use crate::phi_index::{ManifestEntry, PhiManifest};
+use crate::phi_lsh::{LshConfig, PhiLshIndex};
+use crate::phi_meta::PhiMetadata;
+use crate::phi_metric::{best_window, SimilarityMetric, Tolerance, WindowMatch, EXACT_TOLERANCE};
+use chrono::{DateTime, Utc};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
 
 /// Compute similarity between two quantized φ-sequences
+///
+/// Fraction of equal elements, i.e. `Tolerance::new(EXACT_TOLERANCE)`.
 pub fn phi_similarity(a: &[f64], b: &[f64]) -> f64 {
-    if a.len() != b.len() || a.is_empty() {
-        return 0.0;
+    Tolerance::new(EXACT_TOLERANCE).similarity(a, b, None)
+}
+
+/// φ-router: find the best-matching memory entry from store
+pub fn phi_route(input: &[f64], store: &PhiMemoryStore, threshold: f64) -> Option<(String, f64)> {
+    phi_route_with(input, store, threshold, &Tolerance::new(EXACT_TOLERANCE))
+}
+
+/// Like `phi_route`, scoring entries with `metric` and each entry's recorded step
+pub fn phi_route_with(
+    input: &[f64],
+    store: &PhiMemoryStore,
+    threshold: f64,
+    metric: &dyn SimilarityMetric,
+) -> Option<(String, f64)> {
+    phi_route_top_k_with(input, store, 1, threshold, metric)
+        .into_iter()
+        .find(|c| c.score > 0.0)
+        .map(|c| (c.name, c.score))
+}
+
+/// Stored entry with its score against an input
+#[derive(Debug, Clone, PartialEq)]
+pub struct RouteCandidate {
+    pub name: String,
+    pub score: f64,
+}
+
+/// The `k` best entries scoring at least `threshold`, best first
+///
+/// Scores like `phi_route`; entries with equal scores are ranked by name.
+pub fn phi_route_top_k(input: &[f64], store: &PhiMemoryStore, k: usize, threshold: f64) -> Vec<RouteCandidate> {
+    phi_route_top_k_with(input, store, k, threshold, &Tolerance::new(EXACT_TOLERANCE))
+}
+
+/// Like `phi_route_top_k`, scoring entries with `metric`
+pub fn phi_route_top_k_with(
+    input: &[f64],
+    store: &PhiMemoryStore,
+    k: usize,
+    threshold: f64,
+    metric: &dyn SimilarityMetric,
+) -> Vec<RouteCandidate> {
+    let manifest = stored_manifest(store);
+    let entries = store.list().unwrap_or_default().into_iter().filter_map(|name| {
+        let entry = store.load(&name).ok()?;
+        let step = recorded_step(store, &name, manifest.get(&name));
+        Some((name, entry, step))
+    });
+    rank(entries, input, k, threshold, metric, str::cmp)
+}
+
+/// Manifest as stored, empty if missing or unreadable
+///
+/// Routing never rebuilds it: a rebuild reads every entry and fails on the first unreadable
+/// one, which routing skips instead; the sidecar fallbacks below cover missing records.
+fn stored_manifest(store: &PhiMemoryStore) -> PhiManifest {
+    PhiManifest::load(store.base_path()).unwrap_or_default()
+}
+
+/// Routing threshold of a stored entry: from its manifest record, or from its metadata
+/// sidecar when the record has no metadata in it (see `recorded_step`)
+fn recorded_threshold(store: &PhiMemoryStore, name: &str, record: Option<&ManifestEntry>) -> Option<f64> {
+    match record {
+        Some(record) if record.step.is_some() => record.route_threshold,
+        _ => store.read_meta(name).ok().and_then(|meta| meta.route_threshold),
     }
-    let mut matched = 0;
-    for (x, y) in a.iter().zip(b.iter()) {
-        if (x - y).abs() < 1e-6 {
-            matched += 1;
+}
+
+/// Step of a stored entry: from its manifest record, or from its metadata sidecar when the
+/// record has none (metadata saved before the data, or a manifest rebuilt without it)
+fn recorded_step(store: &PhiMemoryStore, name: &str, record: Option<&ManifestEntry>) -> Option<f64> {
+    record
+        .and_then(|r| r.step)
+        .or_else(|| store.read_meta(name).ok().map(|meta| meta.step))
+}
+
+/// Score `(name, data, step)` entries one at a time and keep the `k` best at or above `threshold`
+///
+/// Equal scores are ordered by `tie`, which must be a total order on names.
+fn rank<N: Into<String>, D: AsRef<[f64]>>(
+    entries: impl IntoIterator<Item = (N, D, Option<f64>)>,
+    input: &[f64],
+    k: usize,
+    threshold: f64,
+    metric: &dyn SimilarityMetric,
+    tie: impl Fn(&str, &str) -> Ordering,
+) -> Vec<RouteCandidate> {
+    let mut ranked = Vec::new();
+    for (name, data, step) in entries {
+        let score = metric.similarity(input, data.as_ref(), step);
+        if score >= threshold {
+            ranked.push(RouteCandidate { name: name.into(), score });
         }
     }
-    matched as f64 / a.len() as f64
+    ranked.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| tie(&a.name, &b.name)));
+    ranked.truncate(k);
+    ranked
 }
 
-/// φ-router: find the best-matching memory entry from store
-pub fn phi_route(input: &[f64], store: &PhiMemoryStore, threshold: f64) -> Option<(String, f64)> {
-    let mut best_score = 0.0;
-    let mut best_name = None;
-
-    if let Ok(names) = store.list() {
-        for name in names {
-            if let Ok(entry) = store.load(&name) {
-                let score = phi_similarity(input, &entry);
-                if score > best_score {
-                    best_score = score;
-                    best_name = Some(name);
+/// Element-by-element comparison of an input with a stored entry
+#[derive(Debug, Clone, PartialEq)]
+pub struct RouteExplanation {
+    pub name: String,
+    /// Name of the metric that scored the entry
+    pub metric: String,
+    /// Score under that metric, as routing computes it
+    pub score: f64,
+    /// Largest deviation the metric counts as a match, `None` for metrics without one
+    pub tolerance: Option<f64>,
+    /// Index of the stored sample aligned with `input[0]`, as the metric aligned them
+    /// (see `SimilarityMetric::alignment`); `None` for warping metrics such as `Dtw`
+    pub offset: Option<isize>,
+    /// Indices into `deviations` that are within `tolerance`; empty without one
+    pub matched: Vec<usize>,
+    /// Input minus stored value for each aligned pair, in input order; empty without an alignment
+    pub deviations: Vec<f64>,
+    pub input_len: usize,
+    pub stored_len: usize,
+}
+
+impl RouteExplanation {
+    /// Explain the score `metric` gives `stored`, whose recorded step is `step`
+    pub fn new(name: &str, input: &[f64], stored: &[f64], step: Option<f64>, metric: &dyn SimilarityMetric) -> Self {
+        let tolerance = metric.tolerance(step);
+        let offset = metric.alignment(input, stored, step);
+        let deviations: Vec<f64> = match offset {
+            Some(offset) => {
+                let (input, stored) = match usize::try_from(offset) {
+                    Ok(start) => (input, stored.get(start..).unwrap_or_default()),
+                    Err(_) => (input.get(offset.unsigned_abs()..).unwrap_or_default(), stored),
+                };
+                input.iter().zip(stored).map(|(x, y)| x - y).collect()
+            }
+            None => Vec::new(),
+        };
+        let matched = match tolerance {
+            Some(tolerance) => deviations
+                .iter()
+                .enumerate()
+                .filter(|(_, d)| d.abs() <= tolerance * (1.0 + 1e-9))
+                .map(|(i, _)| i)
+                .collect(),
+            None => Vec::new(),
+        };
+        Self {
+            name: name.to_string(),
+            metric: metric.name().to_string(),
+            score: metric.similarity(input, stored, step),
+            tolerance,
+            offset,
+            matched,
+            deviations,
+            input_len: input.len(),
+            stored_len: stored.len(),
+        }
+    }
+
+    /// Largest absolute deviation, 0 if nothing was compared
+    pub fn max_deviation(&self) -> f64 {
+        self.deviations.iter().fold(0.0, |m, d| m.max(d.abs()))
+    }
+}
+
+/// Explain how `input` compares with the stored entry `name`, typically the routing winner
+///
+/// Scores like `phi_route`.
+pub fn phi_explain(input: &[f64], store: &PhiMemoryStore, name: &str) -> PhiResult<RouteExplanation> {
+    phi_explain_with(input, store, name, &Tolerance::new(EXACT_TOLERANCE))
+}
+
+/// Like `phi_explain`, scoring with `metric`
+pub fn phi_explain_with(
+    input: &[f64],
+    store: &PhiMemoryStore,
+    name: &str,
+    metric: &dyn SimilarityMetric,
+) -> PhiResult<RouteExplanation> {
+    let stored = store.load(name)?;
+    let step = recorded_step(store, name, stored_manifest(store).get(name));
+    Ok(RouteExplanation::new(name, input, &stored, step, metric))
+}
+
+/// Route many inputs against a store, loading every entry once instead of once per input
+///
+/// Scores like `phi_route`; with `threads` above 1 the inputs are split across threads.
+pub fn phi_route_batch(
+    inputs: &[&[f64]],
+    store: &PhiMemoryStore,
+    threshold: f64,
+    threads: usize,
+) -> PhiResult<Vec<Option<(String, f64)>>> {
+    phi_route_batch_with(inputs, store, threshold, threads, &Tolerance::new(EXACT_TOLERANCE))
+}
+
+/// Like `phi_route_batch`, scoring entries with `metric`
+///
+/// Entries that cannot be read are skipped, as in `phi_route`.
+pub fn phi_route_batch_with(
+    inputs: &[&[f64]],
+    store: &PhiMemoryStore,
+    threshold: f64,
+    threads: usize,
+    metric: &dyn SimilarityMetric,
+) -> PhiResult<Vec<Option<(String, f64)>>> {
+    let manifest = stored_manifest(store);
+    let mut entries = Vec::new();
+    for name in store.list()? {
+        if let Ok(data) = store.load(&name) {
+            let step = recorded_step(store, &name, manifest.get(&name));
+            entries.push((name, data, step));
+        }
+    }
+    Ok(map_parallel(inputs, threads, |input| {
+        let scored = entries.iter().map(|(name, data, step)| (name.as_str(), data, *step));
+        rank(scored, input, 1, threshold, metric, str::cmp)
+            .into_iter()
+            .find(|c| c.score > 0.0)
+            .map(|c| (c.name, c.score))
+    }))
+}
+
+/// Route an input with ambiguity and open-set detection; see `PhiRouter::classify`
+///
+/// Scores like `phi_route`.
+pub fn phi_route_outcome(input: &[f64], store: &PhiMemoryStore, policy: &RoutePolicy) -> PhiResult<RouteOutcome> {
+    phi_route_outcome_with(input, store, policy, &Tolerance::new(EXACT_TOLERANCE))
+}
+
+/// Like `phi_route_outcome`, scoring entries with `metric`
+///
+/// Entries that cannot be read are skipped, as in `phi_route`.
+pub fn phi_route_outcome_with(
+    input: &[f64],
+    store: &PhiMemoryStore,
+    policy: &RoutePolicy,
+    metric: &dyn SimilarityMetric,
+) -> PhiResult<RouteOutcome> {
+    let manifest = stored_manifest(store);
+    let ranked = phi_route_top_k_with(input, store, usize::MAX, 0.0, metric);
+    Ok(classify_ranked(ranked, |name| recorded_threshold(store, name, manifest.get(name)), policy))
+}
+
+/// Outcome for candidates ranked best first, given each one's own routing threshold
+fn classify_ranked(
+    ranked: Vec<RouteCandidate>,
+    threshold_of: impl Fn(&str) -> Option<f64>,
+    policy: &RoutePolicy,
+) -> RouteOutcome {
+    let eligible = ranked.iter().position(|c| {
+        let threshold = threshold_of(&c.name).unwrap_or(policy.threshold);
+        c.score > 0.0 && c.score >= threshold
+    });
+    let Some(i) = eligible else {
+        return RouteOutcome::Unknown { best: ranked.into_iter().find(|c| c.score > 0.0) };
+    };
+    let runner_up = ranked.iter().enumerate().find(|(j, _)| *j != i).map(|(_, c)| c.clone());
+    let best = ranked[i].clone();
+    let margin = best.score - runner_up.as_ref().map_or(0.0, |c| c.score);
+    match runner_up {
+        Some(runner_up) if margin < policy.min_margin => RouteOutcome::Ambiguous { best, runner_up },
+        _ => RouteOutcome::Matched { name: best.name, score: best.score, margin },
+    }
+}
+
+/// `route` applied to every input, spread over `threads` scoped threads (0 uses every
+/// available core); results are in input order
+fn map_parallel<T, F>(inputs: &[&[f64]], threads: usize, route: F) -> Vec<T>
+where
+    T: Send,
+    F: Fn(&[f64]) -> T + Sync,
+{
+    let threads = match threads {
+        0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    };
+    if threads <= 1 || inputs.len() <= 1 {
+        return inputs.iter().map(|input| route(input)).collect();
+    }
+    let chunk = inputs.len().div_ceil(threads);
+    let route = &route;
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = inputs
+            .chunks(chunk)
+            .map(|part| scope.spawn(move || part.iter().map(|input| route(input)).collect::<Vec<T>>()))
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|w| w.join().expect("routing thread panicked"))
+            .collect()
+    })
+}
+
+/// Acceptance rules for `PhiRouter::classify`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RoutePolicy {
+    /// Score a match needs, for entries without their own `route_threshold`
+    pub threshold: f64,
+    /// Smallest lead over the runner-up for a match to be accepted rather than ambiguous
+    pub min_margin: f64,
+}
+
+impl Default for RoutePolicy {
+    fn default() -> Self {
+        Self { threshold: 0.8, min_margin: 0.1 }
+    }
+}
+
+/// Result of routing an input under a `RoutePolicy`
+#[derive(Debug, Clone, PartialEq)]
+pub enum RouteOutcome {
+    /// One entry cleared its threshold with a clear lead; `margin` is its lead over the runner-up
+    Matched { name: String, score: f64, margin: f64 },
+    /// The best entry cleared its threshold but the runner-up scored within `min_margin` of it
+    Ambiguous { best: RouteCandidate, runner_up: RouteCandidate },
+    /// No entry cleared its threshold; `best` is the closest entry, if any scored above 0
+    Unknown { best: Option<RouteCandidate> },
+}
+
+impl RouteOutcome {
+    /// Name of the matched entry, `None` unless the outcome is `Matched`
+    pub fn matched(&self) -> Option<&str> {
+        match self {
+            RouteOutcome::Matched { name, .. } => Some(name),
+            _ => None,
+        }
+    }
+}
+
+/// Metadata tag holding an entry's priority for `TieBreak::Priority`, an integer
+pub const PRIORITY_TAG: &str = "priority";
+
+/// How a `PhiRouter` orders entries with equal scores
+///
+/// Every policy falls back to name order, so rankings never depend on the filesystem or on
+/// the order entries were indexed in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TieBreak {
+    /// Names in lexicographic order
+    #[default]
+    Name,
+    /// Most recently updated entry first; entries without a recorded update come last
+    Recent,
+    /// Highest `PRIORITY_TAG` first; untagged or unparsable entries count as 0
+    Priority,
+}
+
+fn priority(tags: &BTreeMap<String, String>) -> i64 {
+    tags.get(PRIORITY_TAG).and_then(|p| p.trim().parse().ok()).unwrap_or(0)
+}
+
+/// Stored sequence, step and routing hints held by a `PhiRouter`
+#[derive(Debug, Clone)]
+struct IndexedEntry {
+    data: Vec<f64>,
+    step: Option<f64>,
+    threshold: Option<f64>,
+    updated_at: Option<DateTime<Utc>>,
+    priority: i64,
+}
+
+/// In-memory routing index over a store
+///
+/// Built once with `from_store`, it keeps every entry's values and step in memory so queries
+/// never touch disk. Writes made through `save`, `save_meta` and `delete` update the store and
+/// the index together; changes made to the store directly need `refresh`.
+///
+/// With an LSH index attached (`with_lsh`) and an `lsh_compatible` metric, queries score only
+/// the entries sharing a bucket with the input, trading some recall for speed on large
+/// pattern libraries.
+pub struct PhiRouter {
+    entries: BTreeMap<String, IndexedEntry>,
+    metric: Box<dyn SimilarityMetric>,
+    lsh: Option<PhiLshIndex>,
+    tie_break: TieBreak,
+}
+
+impl PhiRouter {
+    /// Empty index scoring like `phi_route`
+    pub fn new() -> Self {
+        Self {
+            entries: BTreeMap::new(),
+            metric: Box::new(Tolerance::new(EXACT_TOLERANCE)),
+            lsh: None,
+            tie_break: TieBreak::default(),
+        }
+    }
+
+    /// Load every entry of a store into the index
+    pub fn from_store(store: &PhiMemoryStore) -> PhiResult<Self> {
+        let mut router = Self::new();
+        let manifest = store.manifest()?;
+        for name in store.list()? {
+            let record = manifest.get(&name);
+            router.insert(&name, store.load(&name)?, recorded_step(store, &name, record));
+            router.index_record(&name, record);
+        }
+        Ok(router)
+    }
+
+    pub fn with_metric<M: SimilarityMetric + 'static>(mut self, metric: M) -> Self {
+        self.metric = Box::new(metric);
+        self
+    }
+
+    pub fn metric(&self) -> &dyn SimilarityMetric {
+        self.metric.as_ref()
+    }
+
+    pub fn with_tie_break(mut self, tie_break: TieBreak) -> Self {
+        self.tie_break = tie_break;
+        self
+    }
+
+    pub fn tie_break(&self) -> TieBreak {
+        self.tie_break
+    }
+
+    /// Order of two equally scored entries under the tie-break policy
+    fn tie_order(&self, a: &str, b: &str) -> Ordering {
+        let (ea, eb) = (self.entries.get(a), self.entries.get(b));
+        let by_policy = match self.tie_break {
+            TieBreak::Name => Ordering::Equal,
+            TieBreak::Recent => eb.and_then(|e| e.updated_at).cmp(&ea.and_then(|e| e.updated_at)),
+            TieBreak::Priority => eb.map_or(0, |e| e.priority).cmp(&ea.map_or(0, |e| e.priority)),
+        };
+        by_policy.then_with(|| a.cmp(b))
+    }
+
+    /// Build an LSH index over the current entries; without a configured quantum it uses
+    /// the smallest recorded step
+    ///
+    /// Only metrics that are `lsh_compatible` (`Tolerance`, `Hamming`) query the buckets; the
+    /// others match entries LSH never puts in the same bucket as the input, so routers using
+    /// one keep scanning every entry.
+    pub fn with_lsh(self, config: LshConfig) -> Self {
+        let quantum = self.lsh_quantum(&config);
+        self.with_lsh_index(PhiLshIndex::new(&config, quantum))
+    }
+
+    /// Quantum `with_lsh` builds with for `config`
+    fn lsh_quantum(&self, config: &LshConfig) -> f64 {
+        config
+            .quantum
+            .or_else(|| self.entries.values().filter_map(|e| e.step).reduce(f64::min))
+            .filter(|q| *q > 0.0)
+            .unwrap_or(EXACT_TOLERANCE)
+    }
+
+    /// Attach an existing LSH index, re-indexing entries it is missing or has stale
+    pub fn with_lsh_index(mut self, mut index: PhiLshIndex) -> Self {
+        let stale: Vec<String> = index.names().into_iter().filter(|n| !self.entries.contains_key(n)).collect();
+        for name in stale {
+            index.remove(&name);
+        }
+        for (name, entry) in &self.entries {
+            if !index.is_current(name, &entry.data) {
+                index.insert(name, &entry.data);
+            }
+        }
+        self.lsh = Some(index);
+        self
+    }
+
+    /// Load a store with the LSH index persisted next to it, building and saving one if
+    /// there is none or it was built with another config or quantum
+    pub fn from_store_with_lsh(store: &PhiMemoryStore, config: LshConfig) -> PhiResult<Self> {
+        let router = Self::from_store(store)?;
+        let quantum = router.lsh_quantum(&config);
+        let router = match PhiLshIndex::load(store.base_path())? {
+            Some(index) if index.is_built_with(&config, quantum) => router.with_lsh_index(index),
+            _ => router.with_lsh(config),
+        };
+        router.save_lsh(store)?;
+        Ok(router)
+    }
+
+    pub fn lsh(&self) -> Option<&PhiLshIndex> {
+        self.lsh.as_ref()
+    }
+
+    /// Persist the LSH index, if any, in the store directory
+    pub fn save_lsh(&self, store: &PhiMemoryStore) -> PhiResult<()> {
+        match &self.lsh {
+            Some(index) => index.save(store.base_path()),
+            None => Ok(()),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Indexed names in sorted order
+    pub fn names(&self) -> Vec<String> {
+        self.entries.keys().cloned().collect()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.entries.contains_key(name)
+    }
+
+    /// Add or replace an entry in the index only, keeping the routing hints it had
+    pub fn insert(&mut self, name: &str, data: Vec<f64>, step: Option<f64>) {
+        if let Some(lsh) = &mut self.lsh {
+            lsh.insert(name, &data);
+        }
+        let old = self.entries.get(name);
+        let entry = IndexedEntry {
+            data,
+            step,
+            threshold: old.and_then(|e| e.threshold),
+            updated_at: old.and_then(|e| e.updated_at),
+            priority: old.map_or(0, |e| e.priority),
+        };
+        self.entries.insert(name.to_string(), entry);
+    }
+
+    /// Take an entry's routing threshold, update time and priority from its manifest record
+    fn index_record(&mut self, name: &str, record: Option<&ManifestEntry>) {
+        if let Some(entry) = self.entries.get_mut(name) {
+            entry.threshold = record.and_then(|r| r.route_threshold);
+            entry.updated_at = record.map(|r| r.updated_at);
+            entry.priority = record.map_or(0, |r| priority(&r.tags));
+        }
+    }
+
+    /// Set the score an entry needs in `classify`, in the index only; returns whether it is indexed
+    pub fn set_route_threshold(&mut self, name: &str, threshold: Option<f64>) -> bool {
+        match self.entries.get_mut(name) {
+            Some(entry) => {
+                entry.threshold = threshold;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Drop an entry from the index only; returns whether it was indexed
+    pub fn remove(&mut self, name: &str) -> bool {
+        if let Some(lsh) = &mut self.lsh {
+            lsh.remove(name);
+        }
+        self.entries.remove(name).is_some()
+    }
+
+    /// Save to the store and index the new values
+    ///
+    /// An entry the index did not hold yet takes its step and routing hints from the store,
+    /// as `refresh` does.
+    pub fn save(&mut self, store: &PhiMemoryStore, name: &str, data: &[f64]) -> PhiResult<()> {
+        store.save(name, data)?;
+        let manifest = store.manifest()?;
+        let record = manifest.get(name);
+        match self.entries.get(name) {
+            Some(entry) => {
+                let step = entry.step;
+                self.insert(name, data.to_vec(), step);
+                if let Some(entry) = self.entries.get_mut(name) {
+                    entry.updated_at = record.map(|r| r.updated_at);
                 }
             }
+            None => {
+                self.insert(name, data.to_vec(), recorded_step(store, name, record));
+                self.index_record(name, record);
+            }
         }
+        Ok(())
     }
 
-    if best_score >= threshold {
-        best_name.map(|n| (n, best_score))
-    } else {
-        None
+    /// Save metadata to the store and index its step, routing threshold and priority
+    ///
+    /// Stored data the index did not hold yet is indexed as by `refresh`.
+    pub fn save_meta(&mut self, store: &PhiMemoryStore, name: &str, meta: &PhiMetadata) -> PhiResult<()> {
+        store.save_meta(name, meta)?;
+        match self.entries.get_mut(name) {
+            Some(entry) => {
+                entry.step = Some(meta.step);
+                entry.threshold = meta.route_threshold;
+                entry.updated_at = store.manifest()?.get(name).map(|r| r.updated_at);
+                entry.priority = priority(&meta.tags);
+                Ok(())
+            }
+            None if store.contains(name)? => self.refresh(store, name),
+            None => Ok(()),
+        }
+    }
+
+    /// Delete from the store and the index
+    pub fn delete(&mut self, store: &PhiMemoryStore, name: &str) -> PhiResult<()> {
+        store.delete(name)?;
+        self.remove(name);
+        Ok(())
+    }
+
+    /// Re-read one entry from the store, dropping it if it no longer exists
+    pub fn refresh(&mut self, store: &PhiMemoryStore, name: &str) -> PhiResult<()> {
+        if !store.contains(name)? {
+            self.remove(name);
+            return Ok(());
+        }
+        let manifest = store.manifest()?;
+        let record = manifest.get(name);
+        self.insert(name, store.load(name)?, recorded_step(store, name, record));
+        self.index_record(name, record);
+        Ok(())
+    }
+
+    /// Best entry scoring at least `threshold`, as `phi_route_with` but from memory
+    pub fn route(&self, input: &[f64], threshold: f64) -> Option<(String, f64)> {
+        self.top_k(input, 1, threshold)
+            .into_iter()
+            .find(|c| c.score > 0.0)
+            .map(|c| (c.name, c.score))
+    }
+
+    /// The `k` best entries scoring at least `threshold`, best first, ties ordered by `tie_break`
+    pub fn top_k(&self, input: &[f64], k: usize, threshold: f64) -> Vec<RouteCandidate> {
+        let metric = self.metric.as_ref();
+        let tie = |a: &str, b: &str| self.tie_order(a, b);
+        match &self.lsh {
+            Some(lsh) if metric.lsh_compatible() => {
+                let candidates = lsh.candidates(input);
+                let entries = candidates
+                    .iter()
+                    .filter_map(|name| self.entries.get(name).map(|e| (name.as_str(), &e.data, e.step)));
+                rank(entries, input, k, threshold, metric, tie)
+            }
+            _ => {
+                let entries = self.entries.iter().map(|(name, e)| (name.as_str(), &e.data, e.step));
+                rank(entries, input, k, threshold, metric, tie)
+            }
+        }
+    }
+
+    /// Route `input`, telling a clear match from an ambiguous one or an unknown input
+    ///
+    /// An entry is eligible when it scores above 0 and at least its own `route_threshold`, or
+    /// `policy.threshold` if it has none. The best eligible entry is matched only if it leads
+    /// every other entry, eligible or not, by at least `policy.min_margin`.
+    pub fn classify(&self, input: &[f64], policy: &RoutePolicy) -> RouteOutcome {
+        let ranked = self.top_k(input, usize::MAX, 0.0);
+        classify_ranked(ranked, |name| self.entries.get(name).and_then(|e| e.threshold), policy)
+    }
+
+    /// Route many inputs; results are in input order
+    pub fn route_batch(&self, inputs: &[&[f64]], threshold: f64) -> Vec<Option<(String, f64)>> {
+        inputs.iter().map(|input| self.route(input, threshold)).collect()
+    }
+
+    /// `route_batch` spread over `threads` scoped threads (0 uses every available core)
+    pub fn route_batch_parallel(&self, inputs: &[&[f64]], threshold: f64, threads: usize) -> Vec<Option<(String, f64)>> {
+        map_parallel(inputs, threads, |input| self.route(input, threshold))
+    }
+
+    /// Compare `input` with an indexed entry, `None` if it is not indexed
+    pub fn explain(&self, input: &[f64], name: &str) -> Option<RouteExplanation> {
+        let entry = self.entries.get(name)?;
+        Some(RouteExplanation::new(name, input, &entry.data, entry.step, self.metric.as_ref()))
+    }
+}
+
+impl Default for PhiRouter {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
+/// Route an input that may be shorter, longer or shifted relative to stored entries
+///
+/// Each entry is scored by its best window under `metric` (see `best_window`); the result
+/// carries the offset of the winning alignment. Of entries with equal scores, the first by
+/// name wins.
+pub fn phi_route_window(
+    input: &[f64],
+    store: &PhiMemoryStore,
+    threshold: f64,
+    metric: &dyn SimilarityMetric,
+) -> Option<(String, WindowMatch)> {
+    let mut best: Option<(String, WindowMatch)> = None;
+    let manifest = stored_manifest(store);
+
+    for name in store.list().unwrap_or_default() {
+        if let Ok(entry) = store.load(&name) {
+            let step = recorded_step(store, &name, manifest.get(&name));
+            if let Some(found) = best_window(input, &entry, step, metric) {
+                if best.as_ref().is_none_or(|(_, b)| found.score > b.score) {
+                    best = Some((name, found));
+                }
+            }
+        }
+    }
+    best.filter(|(_, found)| found.score >= threshold)
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::phi_fs::PhiMemoryStore;
+    use crate::phi_metric::{Cosine, Dtw, SlidingWindow};
+    use crate::phi_lsh::LshConfig;
     use std::fs;
     use crate::quantized_memory::phi_quantized_encode;
 
     #[test]
+    #[allow(clippy::useless_vec)]
     fn test_phi_routing_logic() {
         let store = PhiMemoryStore::new(".phi_routing_store");
 
@@ -74,4 +749,303 @@ mod tests {
 
         fs::remove_dir_all(".phi_routing_store").unwrap();
     }
+
+    #[test]
+    fn test_route_with_metric_uses_recorded_step() {
+        let store = PhiMemoryStore::new(".phi_routing_metric_store");
+        store.save("alpha", &[0.10, 0.20, 0.30, 0.40]).unwrap();
+        store.save_meta("alpha", &PhiMetadata::new(10, 0.01, 4)).unwrap();
+        store.save("beta", &[0.90, 0.80, 0.70, 0.60]).unwrap();
+
+        let input = [0.11, 0.21, 0.30, 0.40];
+        assert!(phi_route(&input, &store, 0.8).is_none());
+        let route = phi_route_with(&input, &store, 0.8, &Tolerance::default()).unwrap();
+        assert_eq!(route, ("alpha".to_string(), 1.0));
+        assert_eq!(phi_route_with(&input, &store, 0.9, &Cosine).unwrap().0, "alpha");
+
+        // A sidecar written outside the store leaves the manifest without a step
+        store.save("gamma", &[0.50, 0.50, 0.50, 0.50]).unwrap();
+        PhiMetadata::new(10, 0.05, 4).save("gamma", store.base_path()).unwrap();
+        assert_eq!(store.manifest().unwrap().get("gamma").unwrap().step, None);
+        let input = [0.52, 0.48, 0.54, 0.50];
+        let route = phi_route_with(&input, &store, 0.8, &Tolerance::default()).unwrap();
+        assert_eq!(route, ("gamma".to_string(), 1.0));
+        assert_eq!(phi_route_top_k_with(&input, &store, 1, 0.8, &Tolerance::default())[0].name, "gamma");
+
+        fs::remove_dir_all(".phi_routing_metric_store").unwrap();
+    }
+
+    #[test]
+    fn test_route_short_query_into_long_pattern() {
+        let store = PhiMemoryStore::new(".phi_routing_window_store");
+        let ramp: Vec<f64> = (0..128).map(|i| (i % 32) as f64).collect();
+        let flat = vec![3.0; 128];
+        store.save("ramp", &ramp).unwrap();
+        store.save("flat", &flat).unwrap();
+
+        let query = [10.0, 11.0, 12.0, 13.0];
+        assert!(phi_route(&query, &store, 0.5).is_none());
+        let (name, found) = phi_route_window(&query, &store, 0.9, &Tolerance::default()).unwrap();
+        assert_eq!((name.as_str(), found.offset, found.score), ("ramp", 10, 1.0));
+        assert_eq!(phi_route_with(&query, &store, 0.5, &Dtw::default()).unwrap().0, "ramp");
+
+        fs::remove_dir_all(".phi_routing_window_store").unwrap();
+    }
+
+    #[test]
+    fn test_top_k_ranking_and_explanation() {
+        let store = PhiMemoryStore::new(".phi_routing_top_k_store");
+        store.save("exact", &[0.10, 0.20, 0.30, 0.40]).unwrap();
+        store.save_meta("exact", &PhiMetadata::new(10, 0.01, 4)).unwrap();
+        store.save("half", &[0.10, 0.20, 0.90, 0.90]).unwrap();
+        store.save("none", &[0.90, 0.90, 0.90, 0.90]).unwrap();
+
+        let input = [0.10, 0.20, 0.30, 0.41];
+        let ranked = phi_route_top_k(&input, &store, 5, 0.0);
+        let names: Vec<&str> = ranked.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, vec!["exact", "half", "none"]);
+        assert_eq!(ranked[0].score, 0.75);
+        assert_eq!(phi_route_top_k(&input, &store, 1, 0.0).len(), 1);
+        assert_eq!(phi_route_top_k(&input, &store, 5, 0.6).len(), 1);
+
+        // Explanations score with the metric routing used, so they agree with the ranking
+        let why = phi_explain(&input, &store, &ranked[0].name).unwrap();
+        assert_eq!((why.score, why.tolerance), (ranked[0].score, Some(EXACT_TOLERANCE)));
+        assert_eq!(why.matched, vec![0, 1, 2]);
+        assert!((why.max_deviation() - 0.01).abs() < 1e-12);
+        let why = phi_explain_with(&input, &store, "exact", &Tolerance::default()).unwrap();
+        assert_eq!((why.score, why.tolerance, why.matched.len()), (1.0, Some(0.01), 4));
+        let why = phi_explain_with(&input, &store, "exact", &Cosine).unwrap();
+        assert_eq!((why.metric.as_str(), why.tolerance), ("cosine", None));
+        assert_eq!(why.score, phi_route_top_k_with(&input, &store, 1, 0.0, &Cosine)[0].score);
+        assert!(phi_explain(&input, &store, "missing").is_err());
+
+        // Length-tolerant metrics explain the alignment that scored, or none at all
+        let sliding = SlidingWindow::new(Tolerance::default());
+        let why = RouteExplanation::new("ramp", &[3.0, 4.0], &[1.0, 2.0, 3.0, 4.0], None, &sliding);
+        assert_eq!((why.score, why.offset, why.tolerance), (1.0, Some(2), Some(EXACT_TOLERANCE)));
+        assert_eq!((why.matched, why.deviations), (vec![0, 1], vec![0.0, 0.0]));
+        let why = RouteExplanation::new("ramp", &[9.0, 1.0, 2.0], &[1.0, 2.0], None, &sliding);
+        assert_eq!((why.offset, why.matched.len()), (Some(-1), 2));
+        let why = RouteExplanation::new("ramp", &[3.0, 4.0], &[1.0, 2.0, 3.0, 4.0], None, &Dtw::default());
+        assert_eq!((why.offset, why.deviations.len()), (None, 0));
+
+        fs::remove_dir_all(".phi_routing_top_k_store").unwrap();
+    }
+
+    #[test]
+    fn test_router_index_updates_without_reloading() {
+        let store = PhiMemoryStore::new(".phi_router_index_store");
+        store.save("alpha", &[1.0, 2.0, 3.0]).unwrap();
+        let mut router = PhiRouter::from_store(&store).unwrap().with_metric(Tolerance::default());
+        assert_eq!(router.names(), vec!["alpha"]);
+
+        router.save(&store, "beta", &[0.10, 0.20, 0.30]).unwrap();
+        router.save_meta(&store, "beta", &PhiMetadata::new(10, 0.01, 3)).unwrap();
+        assert_eq!(router.route(&[0.11, 0.20, 0.29], 0.9), Some(("beta".to_string(), 1.0)));
+        assert_eq!(router.explain(&[0.11, 0.20, 0.29], "beta").unwrap().tolerance, Some(0.01));
+
+        // Metadata saved before the data, or for data the index never saw, still counts
+        let mut meta = PhiMetadata::new(10, 0.01, 3);
+        meta.route_threshold = Some(0.5);
+        meta.tags.insert(PRIORITY_TAG.to_string(), "2".to_string());
+        router.save_meta(&store, "gamma", &meta).unwrap();
+        router.save(&store, "gamma", &[5.10, 5.20, 5.30]).unwrap();
+        store.save("delta", &[7.10, 7.20, 7.30]).unwrap();
+        router.save_meta(&store, "delta", &meta).unwrap();
+        for name in ["gamma", "delta"] {
+            let entry = &router.entries[name];
+            assert_eq!((entry.step, entry.threshold, entry.priority), (Some(0.01), Some(0.5), 2));
+            assert!(entry.updated_at.is_some());
+        }
+        assert_eq!(router.route(&[5.11, 5.20, 5.29], 0.9).unwrap().0, "gamma");
+        router.delete(&store, "gamma").unwrap();
+        router.delete(&store, "delta").unwrap();
+
+        // Queries are served from memory, even once the files are gone
+        fs::remove_file(store.base_path().join("alpha.bin")).unwrap();
+        assert_eq!(router.route(&[1.0, 2.0, 3.0], 0.9).unwrap().0, "alpha");
+        router.refresh(&store, "alpha").unwrap();
+        assert!(!router.contains("alpha"));
+
+        router.delete(&store, "beta").unwrap();
+        assert!(router.is_empty());
+        assert!(!store.contains("beta").unwrap());
+
+        fs::remove_dir_all(".phi_router_index_store").unwrap();
+    }
+
+    #[test]
+    fn test_router_with_persisted_lsh_index() {
+        let store = PhiMemoryStore::new(".phi_router_lsh_store");
+        for i in 0..20 {
+            let data: Vec<f64> = (0..16).map(|j| ((i * 16 + j) % 23) as f64 * 0.01).collect();
+            store.save(&format!("p{:02}", i), &data).unwrap();
+            store.save_meta(&format!("p{:02}", i), &PhiMetadata::new(10, 0.01, 16)).unwrap();
+        }
+        let mut router = PhiRouter::from_store_with_lsh(&store, LshConfig::default()).unwrap();
+        assert_eq!((router.lsh().unwrap().len(), router.lsh().unwrap().quantum()), (20, 0.01));
+        assert!(store.base_path().join(crate::phi_lsh::LSH_INDEX_FILE).exists());
+
+        let query = store.load("p07").unwrap();
+        assert_eq!(router.route(&query, 0.9).unwrap().0, "p07");
+        assert!(router.top_k(&query, 20, 0.0).len() < 20);
+
+        router.delete(&store, "p07").unwrap();
+        assert!(router.route(&query, 0.9).is_none());
+        router.save(&store, "fresh", &query).unwrap();
+        assert_eq!(router.route(&query, 0.9).unwrap().0, "fresh");
+
+        // A reloaded index is reconciled with entries saved after it was written
+        let reloaded = PhiRouter::from_store_with_lsh(&store, LshConfig::default()).unwrap();
+        assert_eq!(reloaded.lsh().unwrap().len(), 20);
+        assert_eq!(reloaded.route(&query, 0.9).unwrap().0, "fresh");
+
+        // Asking for another shape rebuilds the saved index instead of reusing it
+        let config = LshConfig { quantum: Some(0.1), ..LshConfig::default() };
+        let rebuilt = PhiRouter::from_store_with_lsh(&store, config).unwrap();
+        assert!(rebuilt.lsh().unwrap().is_built_with(&config, 0.1));
+        assert!(PhiLshIndex::load(store.base_path()).unwrap().unwrap().is_built_with(&config, 0.1));
+
+        // Length-tolerant metrics scan every entry, shorter queries included
+        let dtw = rebuilt.with_metric(Dtw::default());
+        assert_eq!(dtw.top_k(&query[..12], usize::MAX, 0.0).len(), 20);
+
+        fs::remove_dir_all(".phi_router_lsh_store").unwrap();
+    }
+
+    #[test]
+    fn test_lsh_is_skipped_for_scale_invariant_metrics() {
+        let mut router = PhiRouter::new().with_metric(Cosine);
+        router.insert("a", vec![0.1, 0.2, 0.3, 0.4], None);
+        router.insert("b", vec![0.4, 0.3, 0.2, 0.1], None);
+        let router = router.with_lsh(LshConfig::default());
+
+        let scaled = [0.2, 0.4, 0.6, 0.8];
+        let (name, score) = router.route(&scaled, 0.9).unwrap();
+        assert_eq!(name, "a");
+        assert!((score - 1.0).abs() < 1e-12);
+        assert_eq!(router.top_k(&scaled, usize::MAX, 0.0).len(), 2);
+        assert!(router.classify(&scaled, &RoutePolicy::default()).matched().is_some());
+    }
+
+    #[test]
+    fn test_classify_ambiguous_and_unknown_inputs() {
+        let store = PhiMemoryStore::new(".phi_router_outcome_store");
+        store.save("alpha", &[1.0, 2.0, 3.0, 4.0]).unwrap();
+        store.save("beta", &[1.0, 2.0, 3.0, 9.0]).unwrap();
+        store.save("gamma", &[5.0, 6.0, 7.0, 8.0]).unwrap();
+        let policy = RoutePolicy { threshold: 0.7, min_margin: 0.3 };
+
+        // alpha leads beta by a single position
+        let outcome = phi_route_outcome(&[1.0, 2.0, 3.0, 4.0], &store, &policy).unwrap();
+        assert!(matches!(&outcome, RouteOutcome::Ambiguous { best, runner_up }
+            if best.name == "alpha" && runner_up.name == "beta"));
+        let loose = RoutePolicy { min_margin: 0.2, ..policy };
+        assert_eq!(
+            phi_route_outcome(&[1.0, 2.0, 3.0, 4.0], &store, &loose).unwrap(),
+            RouteOutcome::Matched { name: "alpha".to_string(), score: 1.0, margin: 0.25 }
+        );
+        assert_eq!(phi_route_outcome(&[0.0; 4], &store, &policy).unwrap(), RouteOutcome::Unknown { best: None });
+
+        // Other metrics apply, and an unreadable entry is skipped rather than failing the call
+        fs::write(store.base_path().join("broken.bin"), b"PHIZ\x01\x03\x08\0\0\0\0\0\0\0garbage").unwrap();
+        let near = [1.004, 2.0, 3.0, 4.0];
+        let exact = phi_route_outcome(&near, &store, &loose).unwrap();
+        assert!(matches!(exact, RouteOutcome::Matched { score, .. } if score == 0.75));
+        let outcome = phi_route_outcome_with(&near, &store, &loose, &Tolerance::new(0.01)).unwrap();
+        assert!(matches!(outcome, RouteOutcome::Matched { name, score, .. } if name == "alpha" && score == 1.0));
+        fs::remove_file(store.base_path().join("broken.bin")).unwrap();
+
+        // A per-pattern threshold from metadata overrides the policy
+        let mut router = PhiRouter::from_store(&store).unwrap();
+        let outcome = router.classify(&[5.0, 6.0, 0.0, 0.0], &policy);
+        assert_eq!(outcome.matched(), None);
+        let mut meta = PhiMetadata::new(10, 0.01, 4);
+        meta.route_threshold = Some(0.5);
+        router.save_meta(&store, "gamma", &meta).unwrap();
+        assert_eq!(router.classify(&[5.0, 6.0, 0.0, 0.0], &policy).matched(), Some("gamma"));
+        let reloaded = PhiRouter::from_store(&store).unwrap();
+        assert_eq!(reloaded.classify(&[5.0, 6.0, 0.0, 0.0], &policy).matched(), Some("gamma"));
+
+        fs::remove_dir_all(".phi_router_outcome_store").unwrap();
+    }
+
+    #[test]
+    fn test_ties_are_broken_deterministically() {
+        let store = PhiMemoryStore::new(".phi_router_tie_store");
+        for name in ["gamma", "alpha", "beta"] {
+            store.save(name, &[1.0, 2.0, 3.0]).unwrap();
+        }
+        assert_eq!(store.list().unwrap(), vec!["alpha", "beta", "gamma"]);
+        // Update times a minute apart, rather than whatever back-to-back saves record
+        let mut manifest = store.manifest().unwrap();
+        let start = Utc::now() - chrono::Duration::hours(1);
+        for (minutes, name) in [(0, "gamma"), (1, "alpha"), (2, "beta")] {
+            manifest.entries.get_mut(name).unwrap().updated_at = start + chrono::Duration::minutes(minutes);
+        }
+        manifest.save(store.base_path()).unwrap();
+        assert_eq!(phi_route(&[1.0, 2.0, 3.0], &store, 0.9).unwrap().0, "alpha");
+
+        let mut router = PhiRouter::from_store(&store).unwrap();
+        let order = |router: &PhiRouter| -> Vec<String> {
+            router.top_k(&[1.0, 2.0, 3.0], 3, 0.0).into_iter().map(|c| c.name).collect()
+        };
+        assert_eq!(order(&router), vec!["alpha", "beta", "gamma"]);
+
+        router = router.with_tie_break(TieBreak::Recent);
+        assert_eq!(order(&router), vec!["beta", "alpha", "gamma"]);
+        router.save(&store, "alpha", &[1.0, 2.0, 3.0]).unwrap();
+        assert_eq!(router.route(&[1.0, 2.0, 3.0], 0.9).unwrap().0, "alpha");
+
+        let mut meta = PhiMetadata::new(10, 0.01, 3);
+        meta.tags.insert(PRIORITY_TAG.to_string(), "5".to_string());
+        router.save_meta(&store, "gamma", &meta).unwrap();
+        let router = PhiRouter::from_store(&store).unwrap().with_tie_break(TieBreak::Priority);
+        assert_eq!(order(&router), vec!["gamma", "alpha", "beta"]);
+
+        fs::remove_dir_all(".phi_router_tie_store").unwrap();
+    }
+
+    #[test]
+    fn test_route_batch_keeps_input_order() {
+        let store = PhiMemoryStore::new(".phi_router_batch_store");
+        let patterns: Vec<Vec<f64>> = (0..6).map(|i| vec![i as f64, 1.0, 2.0]).collect();
+        for (i, p) in patterns.iter().enumerate() {
+            store.save(&format!("p{}", i), p).unwrap();
+        }
+        let unknown = [9.0, 9.0, 9.0];
+        let mut inputs: Vec<&[f64]> = patterns.iter().rev().map(|p| p.as_slice()).collect();
+        inputs.push(&unknown);
+
+        let expected: Vec<Option<(String, f64)>> =
+            (0..6).rev().map(|i| Some((format!("p{}", i), 1.0))).chain([None]).collect();
+        assert_eq!(phi_route_batch(&inputs, &store, 0.9, 1).unwrap(), expected);
+        assert_eq!(phi_route_batch(&inputs, &store, 0.9, 3).unwrap(), expected);
+        assert_eq!(phi_route_batch(&inputs, &store, 0.9, 0).unwrap(), expected);
+        assert!(phi_route_batch(&[], &store, 0.9, 4).unwrap().is_empty());
+
+        fs::write(store.base_path().join("broken.bin"), b"PHIZ\x01\x03\x08\0\0\0\0\0\0\0garbage").unwrap();
+        let shifted: Vec<Vec<f64>> = patterns.iter().map(|p| p.iter().map(|x| x + 0.004).collect()).collect();
+        let shifted: Vec<&[f64]> = shifted.iter().map(|p| p.as_slice()).collect();
+        let found = phi_route_batch_with(&shifted, &store, 0.9, 2, &Tolerance::new(0.01)).unwrap();
+        let names: Vec<String> = found.into_iter().map(|r| r.unwrap().0).collect();
+        assert_eq!(names, (0..6).map(|i| format!("p{}", i)).collect::<Vec<_>>());
+
+        // Without a manifest, routing reads the store as it is instead of rebuilding one
+        let mut meta = PhiMetadata::new(10, 0.01, 3);
+        meta.route_threshold = Some(1.0);
+        store.save_meta("p0", &meta).unwrap();
+        fs::remove_file(store.base_path().join(crate::phi_index::MANIFEST_FILE)).unwrap();
+        assert_eq!(phi_route_batch(&inputs, &store, 0.9, 2).unwrap(), expected);
+        let policy = RoutePolicy { threshold: 0.5, min_margin: 0.1 };
+        let outcome = phi_route_outcome_with(shifted[0], &store, &policy, &Tolerance::new(0.01)).unwrap();
+        assert_eq!(outcome.matched(), Some("p0"));
+        let outcome = phi_route_outcome_with(&[0.0, 1.0, 9.0], &store, &policy, &Tolerance::new(0.01)).unwrap();
+        assert!(matches!(outcome, RouteOutcome::Unknown { best: Some(c) } if c.name == "p0"));
+        assert_eq!(phi_route_top_k(&patterns[3], &store, 1, 0.9)[0].name, "p3");
+        assert!(!store.base_path().join(crate::phi_index::MANIFEST_FILE).exists());
+
+        fs::remove_dir_all(".phi_router_batch_store").unwrap();
+    }
 }
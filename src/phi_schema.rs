@@ -0,0 +1,201 @@
+//! phi_schema: schema versions of φ-metadata and bundles
+//! Upgrades records written by older releases as they are read
+
+use crate::phi_error::{PhiError, PhiResult};
+use crate::phi_bundle::PhiBundle;
+use crate::phi_fs::PhiMemoryStore;
+use crate::phi_meta::PhiMetadata;
+
+/// Metadata schema written by this build
+///
+/// 1. `n`, `step`, `length`, `saved_at`, without a `schema_version` key
+/// 2. `schema_version` recorded in the file; optional sample rate, unit, description,
+///    source, encoding spec (codec, quantization, table and crate version), routing
+///    threshold, checksum and tags
+///
+/// Optional fields join the current version rather than raising it, so files stay readable
+/// by earlier builds of that version as long as the new fields are unset.
+pub const META_SCHEMA_VERSION: u32 = 2;
+
+/// Bundle schema written by this build
+///
+/// 1. `name`, `metadata`, `data`
+/// 2. `schema_version`, with metadata upgraded to `META_SCHEMA_VERSION`
+pub const BUNDLE_SCHEMA_VERSION: u32 = 2;
+
+/// One step of an upgrade path, from schema `from` to `from + 1`
+pub struct Migration<T> {
+    pub from: u32,
+    pub description: &'static str,
+    pub apply: fn(&mut T),
+}
+
+/// Every metadata migration, in the order they are applied
+pub const META_MIGRATIONS: &[Migration<PhiMetadata>] = &[Migration {
+    from: 1,
+    description: "the schema version is recorded; descriptive fields, encoding spec, checksum and tags start unset",
+    apply: meta_v1_to_v2,
+}];
+
+/// Every bundle migration, in the order they are applied
+pub const BUNDLE_MIGRATIONS: &[Migration<PhiBundle>] = &[Migration {
+    from: 1,
+    description: "the schema version is recorded",
+    apply: bundle_v1_to_v2,
+}];
+
+// Version 2 only added optional fields, which deserialize as unset
+fn meta_v1_to_v2(_: &mut PhiMetadata) {}
+
+fn bundle_v1_to_v2(_: &mut PhiBundle) {}
+
+/// Schema a record was stored with, treating an unversioned one as version 1
+fn stored_version(version: u32, supported: u32) -> PhiResult<u32> {
+    match version {
+        0 => Ok(1),
+        v if v > supported => Err(PhiError::UnsupportedSchema { version: v, supported }),
+        v => Ok(v),
+    }
+}
+
+/// Apply every migration from `stored` onwards, in order
+fn migrate<T>(record: &mut T, stored: u32, migrations: &[Migration<T>]) {
+    for migration in migrations.iter().filter(|m| m.from >= stored) {
+        (migration.apply)(record);
+    }
+}
+
+/// Bring metadata up to `META_SCHEMA_VERSION`; returns the version it was stored with
+///
+/// Records without a version are version 1.
+pub fn upgrade_metadata(meta: &mut PhiMetadata) -> PhiResult<u32> {
+    let stored = stored_version(meta.schema_version, META_SCHEMA_VERSION)?;
+    migrate(meta, stored, META_MIGRATIONS);
+    meta.schema_version = META_SCHEMA_VERSION;
+    Ok(stored)
+}
+
+/// Bring a bundle and its metadata up to date; returns the bundle version it was stored with
+pub fn upgrade_bundle(bundle: &mut PhiBundle) -> PhiResult<u32> {
+    let stored = stored_version(bundle.schema_version, BUNDLE_SCHEMA_VERSION)?;
+    migrate(bundle, stored, BUNDLE_MIGRATIONS);
+    upgrade_metadata(&mut bundle.metadata)?;
+    bundle.schema_version = BUNDLE_SCHEMA_VERSION;
+    Ok(stored)
+}
+
+impl PhiMemoryStore {
+    /// Rewrite sidecars stored with an older schema, keeping their format
+    ///
+    /// Returns the names of the upgraded entries.
+    pub fn upgrade_metadata(&self) -> PhiResult<Vec<String>> {
+        let mut upgraded = Vec::new();
+        for name in self.list()? {
+            let Some(format) = PhiMetadata::detect_format(&name, self.base_path()) else {
+                continue;
+            };
+            let (meta, stored) = self.read_meta_in(&name, self.base_path(), &name)?;
+            if stored < META_SCHEMA_VERSION {
+                self.write_meta(&name, &meta, format)?;
+                upgraded.push(name);
+            }
+        }
+        Ok(upgraded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::phi_meta::{MetaFormat, PhiEncoding};
+    use crate::phi_spec::Quantization;
+    use std::fs;
+
+    const GOLDEN_V1: &str = include_str!("../tests/golden/meta_v1.meta.txt");
+    const GOLDEN_V2: &str = include_str!("../tests/golden/meta_v2.meta.txt");
+    const GOLDEN_V2_JSON: &str = include_str!("../tests/golden/meta_v2.meta.json");
+    const GOLDEN_V2_TOML: &str = include_str!("../tests/golden/meta_v2.meta.toml");
+    const GOLDEN_BUNDLE_V1: &str = include_str!("../tests/golden/bundle_v1.json");
+
+    fn upgraded(text: &str) -> (PhiMetadata, u32) {
+        let (mut meta, issues) = PhiMetadata::parse(text);
+        assert!(issues.is_empty(), "{:?}", issues);
+        let stored = upgrade_metadata(&mut meta).unwrap();
+        (meta, stored)
+    }
+
+    #[test]
+    fn test_migrations_reach_current_versions() {
+        let froms: Vec<u32> = META_MIGRATIONS.iter().map(|m| m.from).collect();
+        assert_eq!(froms, (1..META_SCHEMA_VERSION).collect::<Vec<_>>());
+        let froms: Vec<u32> = BUNDLE_MIGRATIONS.iter().map(|m| m.from).collect();
+        assert_eq!(froms, (1..BUNDLE_SCHEMA_VERSION).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_golden_metadata_versions() {
+        let (v1, stored) = upgraded(GOLDEN_V1);
+        assert_eq!(stored, 1);
+        assert_eq!((v1.n, v1.step, v1.length), (10, 0.01, 4));
+        assert_eq!(v1.schema_version, META_SCHEMA_VERSION);
+        assert_eq!(v1.encoding_spec(), None);
+
+        let (v2, stored) = upgraded(GOLDEN_V2);
+        assert_eq!(stored, 2);
+        assert_eq!(v2.description.as_deref(), Some("resting ECG\nlead II"));
+        assert_eq!(v2.tags["label"], "a=b");
+        assert_eq!(v2.encoding, Some(PhiEncoding::PhiQuantized));
+        assert_eq!(v2.quantization, Some(Quantization::Round));
+        assert_eq!(v2.checksum, Some(0xdead_beef));
+
+        // The same record in every sidecar format
+        let mut v2_json: PhiMetadata = serde_json::from_str(GOLDEN_V2_JSON).unwrap();
+        assert_eq!(upgrade_metadata(&mut v2_json).unwrap(), 2);
+        assert_eq!(v2_json, v2);
+        let mut v2_toml: PhiMetadata = toml::from_str(GOLDEN_V2_TOML).unwrap();
+        assert_eq!(upgrade_metadata(&mut v2_toml).unwrap(), 2);
+        assert_eq!(v2_toml, v2);
+
+        // Current records carry their version and round-trip unchanged
+        let (current, stored) = upgraded(&v2.to_text());
+        assert_eq!(stored, META_SCHEMA_VERSION);
+        assert_eq!(current, v2);
+    }
+
+    #[test]
+    fn test_golden_bundle_and_future_versions() {
+        let mut bundle: PhiBundle = serde_json::from_str(GOLDEN_BUNDLE_V1).unwrap();
+        assert_eq!(upgrade_bundle(&mut bundle).unwrap(), 1);
+        assert_eq!(bundle.schema_version, BUNDLE_SCHEMA_VERSION);
+        assert_eq!(bundle.metadata.schema_version, META_SCHEMA_VERSION);
+        assert_eq!(bundle.data, vec![4.2, -1.5]);
+
+        let future = format!("schema_version={}\n{}", META_SCHEMA_VERSION + 1, GOLDEN_V1);
+        let (mut meta, _) = PhiMetadata::parse(&future);
+        assert!(matches!(upgrade_metadata(&mut meta), Err(PhiError::UnsupportedSchema { version, .. }) if version == META_SCHEMA_VERSION + 1));
+    }
+
+    #[test]
+    fn test_store_upgrade_rewrites_old_sidecars() {
+        let store = PhiMemoryStore::new(".phi_schema_store");
+        store.save("old", &[0.0; 4]).unwrap();
+        store.save("older", &[0.0; 4]).unwrap();
+        store.save("new", &[0.0; 4]).unwrap();
+        fs::write(".phi_schema_store/old.meta.txt", GOLDEN_V1).unwrap();
+        fs::write(".phi_schema_store/older.meta.toml", GOLDEN_V2_TOML).unwrap();
+        store.save_meta("new", &PhiMetadata::new(10, 0.01, 4)).unwrap();
+
+        // Reads upgrade in memory without touching the file
+        assert_eq!(store.load_meta("old").unwrap().schema_version, META_SCHEMA_VERSION);
+        assert_eq!(fs::read_to_string(".phi_schema_store/old.meta.txt").unwrap(), GOLDEN_V1);
+
+        let mut upgraded = store.upgrade_metadata().unwrap();
+        upgraded.sort();
+        assert_eq!(upgraded, vec!["old"]);
+        assert_eq!(PhiMetadata::detect_format("older", store.base_path()), Some(MetaFormat::Toml));
+        assert_eq!(PhiMetadata::load_versioned("old", store.base_path()).unwrap().1, META_SCHEMA_VERSION);
+        assert!(store.upgrade_metadata().unwrap().is_empty());
+
+        fs::remove_dir_all(".phi_schema_store").unwrap();
+    }
+}
@@ -0,0 +1,305 @@
+//! phi_sign: Ed25519 signatures showing who produced a bundle or archive
+//! Signatures are embedded in bundles or kept next to a file as `<file>.sig` (feature `signing`)
+
+use crate::phi_error::PhiResult;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Signer's public key and signature, both hex-encoded
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BundleSignature {
+    pub public_key: String,
+    pub signature: String,
+}
+
+impl BundleSignature {
+    /// Write as a detached JSON signature file
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> PhiResult<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> PhiResult<Self> {
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+}
+
+/// Path of the detached signature for a file: `<file>.sig`
+pub fn signature_path<P: AsRef<Path>>(path: P) -> PathBuf {
+    let mut name = path.as_ref().as_os_str().to_owned();
+    name.push(".sig");
+    PathBuf::from(name)
+}
+
+#[cfg(feature = "signing")]
+pub use self::ed::*;
+
+#[cfg(feature = "signing")]
+mod ed {
+    use super::*;
+    use ed25519_dalek::{Signature, Signer, SigningKey, StreamVerifier, VerifyingKey};
+    use crate::phi_error::PhiError;
+    use std::collections::BTreeMap;
+
+    fn to_hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    fn from_hex<const N: usize>(hex: &str) -> PhiResult<[u8; N]> {
+        let invalid = || PhiError::format(format!("expected {} hex-encoded bytes", N));
+        if hex.len() != 2 * N || !hex.is_ascii() {
+            return Err(invalid());
+        }
+        let mut out = [0u8; N];
+        for (i, byte) in out.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).map_err(|_| invalid())?;
+        }
+        Ok(out)
+    }
+
+    /// Ed25519 private key used to sign bundles and archives
+    pub struct PhiSigner(SigningKey);
+
+    impl PhiSigner {
+        /// Fresh random key
+        pub fn generate() -> Self {
+            Self(SigningKey::generate(&mut rand::rngs::OsRng))
+        }
+
+        pub fn from_bytes(secret: [u8; 32]) -> Self {
+            Self(SigningKey::from_bytes(&secret))
+        }
+
+        pub fn to_bytes(&self) -> [u8; 32] {
+            self.0.to_bytes()
+        }
+
+        /// Hex-encoded public key to hand to whoever verifies
+        pub fn public_key(&self) -> String {
+            to_hex(self.0.verifying_key().as_bytes())
+        }
+
+        pub fn sign(&self, message: &[u8]) -> BundleSignature {
+            BundleSignature {
+                public_key: self.public_key(),
+                signature: to_hex(&self.0.sign(message).to_bytes()),
+            }
+        }
+    }
+
+    impl std::fmt::Debug for PhiSigner {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "PhiSigner({})", self.public_key())
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct TrustedKeysFile {
+        #[serde(default)]
+        keys: BTreeMap<String, String>,
+    }
+
+    /// Public keys whose signatures are accepted, each under a label naming its owner
+    ///
+    /// Stored as TOML: a `[keys]` table mapping labels to hex public keys.
+    #[derive(Debug, Clone, Default)]
+    pub struct TrustedKeys {
+        keys: BTreeMap<String, VerifyingKey>,
+    }
+
+    impl TrustedKeys {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        /// Trust a hex-encoded public key under `label`
+        pub fn add(&mut self, label: &str, public_key: &str) -> PhiResult<()> {
+            let key = VerifyingKey::from_bytes(&from_hex::<32>(public_key)?)
+                .map_err(|_| PhiError::format("not an Ed25519 public key"))?;
+            self.keys.insert(label.to_string(), key);
+            Ok(())
+        }
+
+        pub fn with_key(mut self, label: &str, public_key: &str) -> PhiResult<Self> {
+            self.add(label, public_key)?;
+            Ok(self)
+        }
+
+        pub fn labels(&self) -> Vec<String> {
+            self.keys.keys().cloned().collect()
+        }
+
+        pub fn load<P: AsRef<Path>>(path: P) -> PhiResult<Self> {
+            let text = fs::read_to_string(path)?;
+            let file: TrustedKeysFile = toml::from_str(&text).map_err(|e| PhiError::parse("trusted keys", e))?;
+            let mut trusted = Self::new();
+            for (label, key) in &file.keys {
+                trusted.add(label, key)?;
+            }
+            Ok(trusted)
+        }
+
+        pub fn save<P: AsRef<Path>>(&self, path: P) -> PhiResult<()> {
+            let keys = self.keys.iter().map(|(l, k)| (l.clone(), to_hex(k.as_bytes()))).collect();
+            let text = toml::to_string(&TrustedKeysFile { keys }).map_err(|e| PhiError::format(e.to_string()))?;
+            fs::write(path, text)?;
+            Ok(())
+        }
+
+        /// Check a signature over `message`; returns the label of the trusted signer
+        pub fn verify(&self, message: &[u8], signature: &BundleSignature) -> PhiResult<String> {
+            let mut stream = self.verify_stream(signature)?;
+            stream.update(message);
+            stream.finish()
+        }
+
+        /// Start checking a signature over a message fed in pieces
+        ///
+        /// Fails at once if the signer is not trusted.
+        pub fn verify_stream(&self, signature: &BundleSignature) -> PhiResult<SignatureStream> {
+            let public_key = from_hex::<32>(&signature.public_key)?;
+            let (label, key) = self
+                .keys
+                .iter()
+                .find(|(_, key)| key.as_bytes() == &public_key)
+                .ok_or_else(|| PhiError::Validation(format!("signed by untrusted key {}", signature.public_key)))?;
+            let signature = Signature::from_bytes(&from_hex::<64>(&signature.signature)?);
+            let verifier = key.verify_stream(&signature).map_err(|_| mismatch())?;
+            Ok(SignatureStream { label: label.clone(), verifier })
+        }
+    }
+
+    fn mismatch() -> PhiError {
+        PhiError::Validation("signature does not match the signed content".to_string())
+    }
+
+    /// Signature check over a message that arrives in pieces, from `TrustedKeys::verify_stream`
+    pub struct SignatureStream {
+        label: String,
+        verifier: StreamVerifier,
+    }
+
+    impl SignatureStream {
+        pub fn update(&mut self, bytes: &[u8]) {
+            self.verifier.update(bytes);
+        }
+
+        /// Label of the trusted signer if the signature covers everything fed in
+        pub fn finish(self) -> PhiResult<String> {
+            self.verifier.finalize_and_verify().map_err(|_| mismatch())?;
+            Ok(self.label)
+        }
+    }
+}
+
+#[cfg(all(test, feature = "signing"))]
+mod tests {
+    use super::*;
+    use crate::phi_archive::{ArchiveFilter, PhiArchive};
+    use crate::phi_bundle::{ImportOutcome, ImportPolicy, LoadOptions, PhiBundle};
+    use crate::phi_fs::PhiMemoryStore;
+    use crate::phi_meta::PhiMetadata;
+
+    #[test]
+    fn test_sign_verify_and_trust() {
+        let signer = PhiSigner::generate();
+        let trusted = TrustedKeys::new().with_key("lab-a", &signer.public_key()).unwrap();
+        let signature = signer.sign(b"phi");
+        assert_eq!(trusted.verify(b"phi", &signature).unwrap(), "lab-a");
+        assert!(trusted.verify(b"psi", &signature).is_err());
+        assert!(TrustedKeys::new().verify(b"phi", &signature).is_err());
+
+        let path = "test_trusted_keys.toml";
+        trusted.save(path).unwrap();
+        assert_eq!(TrustedKeys::load(path).unwrap().labels(), vec!["lab-a"]);
+        fs::remove_file(path).unwrap();
+
+        let restored = PhiSigner::from_bytes(signer.to_bytes());
+        assert_eq!(restored.public_key(), signer.public_key());
+    }
+
+    #[test]
+    fn test_embedded_and_detached_bundle_signatures() {
+        let signer = PhiSigner::generate();
+        let trusted = TrustedKeys::new().with_key("lab-a", &signer.public_key()).unwrap();
+        let mut bundle = PhiBundle::new("sig", PhiMetadata::new(10, 0.01, 2), vec![1.0, 2.0]);
+
+        let detached = "test_bundle_detached.json";
+        bundle.save_json(detached).unwrap();
+        assert!(PhiBundle::load_verified(detached, &trusted).is_err());
+        bundle.sign_detached(&signer).unwrap().save(signature_path(detached)).unwrap();
+        let (loaded, by) = PhiBundle::load_verified(detached, &trusted).unwrap();
+        assert_eq!((loaded.data, by.as_str()), (vec![1.0, 2.0], "lab-a"));
+        let verified = LoadOptions::verified_by(&trusted);
+        assert_eq!(PhiBundle::load_json(detached, &verified).unwrap().data, vec![1.0, 2.0]);
+        assert!(PhiBundle::load_json(detached, &LoadOptions::verified_by(&TrustedKeys::new())).is_err());
+
+        let embedded = "test_bundle_embedded.json";
+        bundle.sign(&signer).unwrap();
+        bundle.save_json(embedded).unwrap();
+        assert!(PhiBundle::load_verified(embedded, &trusted).is_ok());
+        assert!(PhiBundle::load_verified(embedded, &TrustedKeys::new()).is_err());
+
+        // Binary bundles are checked the same way
+        let binary = "test_bundle_embedded.phib";
+        bundle.save_binary(binary).unwrap();
+        assert_eq!(PhiBundle::load_verified(binary, &trusted).unwrap().1, "lab-a");
+
+        // Streamed imports check the signature before anything enters the store
+        let store = PhiMemoryStore::new(".phi_sign_stream_store");
+        let (outcome, by) = store.import_bundle_stream_verified(binary, ImportPolicy::Reject, &trusted).unwrap();
+        assert_eq!((outcome, by.as_str()), (ImportOutcome::Imported("sig".into()), "lab-a"));
+        let untrusted = store.import_bundle_stream_verified(binary, ImportPolicy::Overwrite, &TrustedKeys::new());
+        assert!(untrusted.is_err());
+        let mut forged = PhiBundle::new("sig", PhiMetadata::new(10, 0.01, 2), vec![9.0, 2.0]);
+        forged.signature = bundle.signature.clone();
+        forged.save_binary(binary).unwrap();
+        assert!(store.import_bundle_stream_verified(binary, ImportPolicy::Overwrite, &trusted).is_err());
+        assert_eq!(store.load("sig").unwrap(), vec![1.0, 2.0]);
+        fs::remove_dir_all(".phi_sign_stream_store").unwrap();
+
+        // Editing a signed bundle, even with a fresh content hash, breaks the signature
+        bundle.data[0] = 9.0;
+        bundle.save_json(embedded).unwrap();
+        assert!(PhiBundle::load_verified(embedded, &trusted).is_err());
+
+        for path in [detached, embedded, binary] {
+            fs::remove_file(path).unwrap();
+        }
+        fs::remove_file(signature_path(detached)).unwrap();
+    }
+
+    #[test]
+    fn test_archive_signature() {
+        let store = PhiMemoryStore::new(".phi_sign_store");
+        store.save("a", &[1.0]).unwrap();
+        store.save_meta("a", &PhiMetadata::new(10, 0.01, 1)).unwrap();
+        let signer = PhiSigner::generate();
+        let trusted = TrustedKeys::new().with_key("lab-a", &signer.public_key()).unwrap();
+
+        let path = ".phi_sign_store.phia";
+        let archive = PhiArchive::export(&store, path, &ArchiveFilter::default()).unwrap();
+        archive.sign(&signer).unwrap();
+        let (verified, by) = PhiArchive::open_verified(path, &trusted).unwrap();
+        assert_eq!(by, "lab-a");
+
+        // Entries come from the file that was verified, even once another replaces it
+        store.save("a", &[2.0]).unwrap();
+        let swapped = ".phi_sign_store.swapped.phia";
+        PhiArchive::export(&store, swapped, &ArchiveFilter::default()).unwrap();
+        fs::rename(swapped, path).unwrap();
+        assert_eq!(verified.extract("a").unwrap().data, vec![1.0]);
+        assert!(PhiArchive::open_verified(path, &trusted).is_err());
+        PhiArchive::export(&store, path, &ArchiveFilter::default()).unwrap().sign(&signer).unwrap();
+
+        let mut bytes = fs::read(path).unwrap();
+        bytes[6] ^= 1;
+        fs::write(path, bytes).unwrap();
+        assert!(PhiArchive::open_verified(path, &trusted).is_err());
+
+        fs::remove_file(path).unwrap();
+        fs::remove_file(signature_path(path)).unwrap();
+        fs::remove_dir_all(".phi_sign_store").unwrap();
+    }
+}
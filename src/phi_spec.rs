@@ -0,0 +1,163 @@
+//! phi_spec: complete description of how a φ-memory was encoded
+//! Recorded in metadata so stored data can be decoded without outside knowledge
+
+use crate::core::{hybrid_phi_approximate, hybrid_phi_inverse, PHI_TABLE};
+use crate::phi_meta::PhiEncoding;
+use crate::quantized_memory::{phi_quantized_decode, phi_quantized_encode};
+use serde::{Deserialize, Serialize};
+
+/// Version of `PHI_TABLE`; bump whenever the table values change
+pub const PHI_TABLE_VERSION: u32 = 1;
+
+/// Version of this crate, recorded alongside every spec
+pub const CRATE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Quantization applied after φ-approximation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Quantization {
+    /// Values kept at full precision
+    None,
+    /// `round(approx / step) * step`
+    Round,
+}
+
+impl Quantization {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Quantization::None => "none",
+            Quantization::Round => "round",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "none" => Some(Quantization::None),
+            "round" => Some(Quantization::Round),
+            _ => None,
+        }
+    }
+}
+
+/// Codec, parameters and versions needed to reproduce or invert an encoding
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EncodingSpec {
+    pub codec: PhiEncoding,
+    pub n: usize,
+    pub step: f64,
+    pub quantization: Quantization,
+    pub table_version: u32,
+    pub crate_version: String,
+}
+
+impl EncodingSpec {
+    fn with(codec: PhiEncoding, n: usize, step: f64, quantization: Quantization) -> Self {
+        Self {
+            codec,
+            n,
+            step,
+            quantization,
+            table_version: PHI_TABLE_VERSION,
+            crate_version: CRATE_VERSION.to_string(),
+        }
+    }
+
+    /// Values stored as given
+    pub fn raw() -> Self {
+        Self::with(PhiEncoding::Raw, 1, 0.0, Quantization::None)
+    }
+
+    /// `hybrid_phi_approximate` at order `n`
+    pub fn phi(n: usize) -> Self {
+        Self::with(PhiEncoding::Phi, n, 0.0, Quantization::None)
+    }
+
+    /// `phi_quantized_encode` at order `n` with quantization `step`
+    pub fn phi_quantized(n: usize, step: f64) -> Self {
+        Self::with(PhiEncoding::PhiQuantized, n, step, Quantization::Round)
+    }
+
+    /// Check this build can decode data written with the spec
+    pub fn check_supported(&self) -> Result<(), String> {
+        if self.table_version > PHI_TABLE_VERSION {
+            return Err(format!(
+                "φ-table version {} is newer than supported version {}",
+                self.table_version, PHI_TABLE_VERSION
+            ));
+        }
+        if self.codec != PhiEncoding::Raw && !(1..=PHI_TABLE.len()).contains(&self.n) {
+            return Err(format!("n = {} is outside 1..={}", self.n, PHI_TABLE.len()));
+        }
+        match (self.codec, self.quantization) {
+            (PhiEncoding::PhiQuantized, Quantization::Round) if self.step > 0.0 && self.step.is_finite() => Ok(()),
+            (PhiEncoding::PhiQuantized, Quantization::Round) => Err(format!("invalid quantization step {}", self.step)),
+            (PhiEncoding::PhiQuantized, Quantization::None) => Err("phi-quantized codec without quantization".to_string()),
+            (_, Quantization::Round) => Err(format!("codec '{}' cannot be quantized", self.codec.as_str())),
+            (_, Quantization::None) => Ok(()),
+        }
+    }
+
+    /// Encode original values according to the spec
+    pub fn encode(&self, values: &[f64]) -> Vec<f64> {
+        match self.codec {
+            PhiEncoding::Raw => values.to_vec(),
+            PhiEncoding::Phi => values.iter().map(|&w| hybrid_phi_approximate(w, self.n)).collect(),
+            PhiEncoding::PhiQuantized => values.iter().map(|&w| phi_quantized_encode(w, self.n, self.step)).collect(),
+        }
+    }
+
+    /// Reconstruct original values from stored data
+    pub fn decode(&self, stored: &[f64]) -> Result<Vec<f64>, String> {
+        self.check_supported()?;
+        Ok(match self.codec {
+            PhiEncoding::Raw => stored.to_vec(),
+            PhiEncoding::Phi => stored.iter().map(|&w| hybrid_phi_inverse(w, self.n)).collect(),
+            PhiEncoding::PhiQuantized => stored.iter().map(|&q| phi_quantized_decode(q, self.n)).collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::phi_fs::PhiMemoryStore;
+    use crate::phi_error::PhiError;
+    use crate::phi_meta::PhiMetadata;
+    use std::fs;
+
+    #[test]
+    fn test_spec_support_checks() {
+        assert!(EncodingSpec::phi_quantized(10, 0.01).check_supported().is_ok());
+        assert!(EncodingSpec::raw().check_supported().is_ok());
+        assert!(EncodingSpec::phi(0).check_supported().is_err());
+        assert!(EncodingSpec::phi_quantized(10, 0.0).check_supported().is_err());
+
+        let mut future = EncodingSpec::phi(10);
+        future.table_version = PHI_TABLE_VERSION + 1;
+        assert!(future.decode(&[1.0]).is_err());
+    }
+
+    #[test]
+    fn test_load_decoded_from_recorded_spec() {
+        let store = PhiMemoryStore::new(".phi_spec_store");
+        let original = [1.0, 2.0, 42.0];
+        let spec = EncodingSpec::phi_quantized(10, 0.001);
+        let encoded = spec.encode(&original);
+        store.save("wave", &encoded).unwrap();
+        store.save_meta("wave", &PhiMetadata::for_spec(&spec, encoded.len())).unwrap();
+
+        let meta = store.load_meta("wave").unwrap();
+        assert_eq!(meta.encoding_spec(), Some(spec));
+
+        let decoded = store.load_decoded("wave").unwrap();
+        for (w, r) in original.iter().zip(decoded.iter()) {
+            assert!((w - r).abs() < 0.001 * 1.5, "w = {}, r = {}", w, r);
+        }
+
+        // Metadata without an encoding cannot be decoded
+        store.save_meta("wave", &PhiMetadata::new(10, 0.001, 3)).unwrap();
+        assert!(matches!(store.load_decoded("wave"), Err(PhiError::Format(_))));
+
+        fs::remove_dir_all(".phi_spec_store").unwrap();
+    }
+}
@@ -0,0 +1,470 @@
+//! phi_stream: binary bundles read and written chunk by chunk
+//! Moves memories larger than RAM between a store and a bundle file in bounded memory
+
+use crate::phi_bundle::{
+    binary_prefix, content_hasher, content_prefix, header_issues, read_binary_header, read_field, resolve_import_name, BundleIssue,
+    BundleReport, ExportView, ImportOutcome, ImportPolicy, PhiBundle,
+};
+#[cfg(feature = "signing")]
+use crate::phi_bundle::{bundle_signature, SIGNING_DOMAIN};
+use crate::phi_compress::{raw_bytes, raw_values, Compression, PayloadReader, PayloadWriter};
+use crate::phi_error::{PhiError, PhiResult};
+use crate::phi_fs::PhiMemoryStore;
+use crate::phi_index::Fnv1a64;
+use crate::phi_meta::PhiMetadata;
+use crate::phi_schema::{upgrade_bundle, BUNDLE_SCHEMA_VERSION};
+use crate::phi_sign::BundleSignature;
+#[cfg(feature = "signing")]
+use crate::phi_sign::{SignatureStream, TrustedKeys};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Values per chunk unless changed with `with_chunk_len`
+pub const DEFAULT_CHUNK_LEN: usize = 64 * 1024;
+
+/// Stands in for the content hash until `BundleWriter::finish` knows it
+const HASH_PLACEHOLDER: &str = "0000000000000000";
+
+/// Reads the values of a binary bundle in chunks, checking the content hash at the end
+pub struct BundleReader {
+    reader: BufReader<File>,
+    name: String,
+    metadata: PhiMetadata,
+    content_hash: Option<String>,
+    hasher: Fnv1a64,
+    len: usize,
+    remaining: usize,
+    chunk_len: usize,
+    #[cfg(feature = "signing")]
+    signature: Option<SignatureStream>,
+    #[cfg(feature = "signing")]
+    signer: Option<String>,
+}
+
+impl BundleReader {
+    /// Open a binary bundle, reading only its header
+    pub fn open<P: AsRef<Path>>(path: P) -> PhiResult<Self> {
+        Ok(Self::open_unchecked(path.as_ref())?.0)
+    }
+
+    /// `open` for a bundle that must be signed by a trusted key
+    ///
+    /// The embedded signature is used if there is one, otherwise `<path>.sig`. The chunk
+    /// that completes the payload fails with `PhiError::Validation` if the signature does
+    /// not match; `signer` names the key once it does.
+    #[cfg(feature = "signing")]
+    pub fn open_verified<P: AsRef<Path>>(path: P, trusted: &TrustedKeys) -> PhiResult<Self> {
+        let path = path.as_ref();
+        let (mut reader, embedded, prefix) = Self::open_unchecked(path)?;
+        let mut stream = trusted.verify_stream(&bundle_signature(path, embedded.as_ref())?)?;
+        stream.update(SIGNING_DOMAIN);
+        stream.update(&prefix);
+        reader.signature = Some(stream);
+        Ok(reader)
+    }
+
+    /// Reader with its embedded signature and the name and metadata as the hash covers them
+    fn open_unchecked(path: &Path) -> PhiResult<(Self, Option<BundleSignature>, Vec<u8>)> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let (header, payload_len) = read_binary_header(&mut reader)?;
+        if payload_len % 8 != 0 {
+            return Err(PhiError::format("binary bundle payload is not a whole number of values"));
+        }
+        let len = usize::try_from(payload_len / 8).map_err(|_| PhiError::format("binary bundle is too large"))?;
+        // The hash covers the metadata as stored, so take it before upgrading
+        let prefix = content_prefix(&header.name, &header.metadata)?;
+        let hasher = content_hasher(&header.name, &header.metadata)?;
+        let mut bundle = PhiBundle {
+            schema_version: header.schema_version,
+            name: header.name,
+            metadata: header.metadata,
+            content_hash: header.content_hash,
+            signature: None,
+            data: Vec::new(),
+        };
+        upgrade_bundle(&mut bundle)?;
+        let reader = Self {
+            reader,
+            name: bundle.name,
+            metadata: bundle.metadata,
+            content_hash: bundle.content_hash,
+            hasher,
+            len,
+            remaining: len,
+            chunk_len: DEFAULT_CHUNK_LEN,
+            #[cfg(feature = "signing")]
+            signature: None,
+            #[cfg(feature = "signing")]
+            signer: None,
+        };
+        Ok((reader, header.signature, prefix))
+    }
+
+    /// Read at most `chunk_len` values per chunk
+    pub fn with_chunk_len(mut self, chunk_len: usize) -> Self {
+        self.chunk_len = chunk_len.max(1);
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn metadata(&self) -> &PhiMetadata {
+        &self.metadata
+    }
+
+    /// Number of values in the payload
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Label of the trusted key that signed the bundle, once `open_verified` checked the whole payload
+    #[cfg(feature = "signing")]
+    pub fn signer(&self) -> Option<&str> {
+        self.signer.as_deref()
+    }
+
+    /// Next chunk of values, `None` when done
+    ///
+    /// The chunk that completes the payload fails with `PhiError::Validation` if the
+    /// bundle's content hash does not match.
+    pub fn next_chunk(&mut self) -> PhiResult<Option<Vec<f64>>> {
+        Ok(self.next_bytes()?.map(|bytes| raw_values(&bytes)))
+    }
+
+    /// Read and check the rest of the payload without keeping it
+    pub fn finish(&mut self) -> PhiResult<()> {
+        while self.next_bytes()?.is_some() {}
+        Ok(())
+    }
+
+    fn next_bytes(&mut self) -> PhiResult<Option<Vec<u8>>> {
+        if self.remaining == 0 {
+            self.check_complete()?;
+            return Ok(None);
+        }
+        let count = self.chunk_len.min(self.remaining);
+        let mut bytes = vec![0u8; count * 8];
+        read_field(&mut self.reader, &mut bytes)?;
+        self.hasher.update(&bytes);
+        #[cfg(feature = "signing")]
+        if let Some(stream) = &mut self.signature {
+            stream.update(&bytes);
+        }
+        self.remaining -= count;
+        if self.remaining == 0 {
+            self.check_complete()?;
+        }
+        Ok(Some(bytes))
+    }
+
+    /// Check the content hash, and the signature for readers from `open_verified`
+    fn check_complete(&mut self) -> PhiResult<()> {
+        let actual = format!("{:016x}", self.hasher.finish());
+        if let Some(recorded) = self.content_hash.as_ref().filter(|recorded| **recorded != actual) {
+            return Err(PhiError::Validation(format!(
+                "content hash {} does not match bundle contents ({})",
+                recorded, actual
+            )));
+        }
+        #[cfg(feature = "signing")]
+        if let Some(stream) = self.signature.take() {
+            self.signer = Some(stream.finish()?);
+        }
+        Ok(())
+    }
+}
+
+/// Writes a binary bundle whose values arrive in chunks, hashing them on the way
+pub struct BundleWriter {
+    out: BufWriter<File>,
+    hasher: Fnv1a64,
+    hash_offset: u64,
+    expected: usize,
+    written: usize,
+}
+
+impl BundleWriter {
+    /// Start a bundle holding `metadata.length` values
+    ///
+    /// The content hash is computed as the values are written and filled in by `finish`.
+    pub fn create<P: AsRef<Path>>(path: P, name: &str, metadata: &PhiMetadata) -> PhiResult<Self> {
+        let view = ExportView {
+            schema_version: BUNDLE_SCHEMA_VERSION,
+            name,
+            metadata,
+            content_hash: Some(HASH_PLACEHOLDER.to_string()),
+            signature: None,
+            data: None,
+        };
+        let prefix = binary_prefix(&view, metadata.length as u64 * 8)?;
+        // The hash is the last field of the header, so search from the end
+        let field = format!("\"content_hash\":\"{}\"", HASH_PLACEHOLDER);
+        let start = prefix
+            .windows(field.len())
+            .rposition(|window| window == field.as_bytes())
+            .ok_or_else(|| PhiError::format("bundle header has no content hash"))?;
+        let hash_offset = (start + field.len() - HASH_PLACEHOLDER.len() - 1) as u64;
+
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(&prefix)?;
+        let hasher = content_hasher(name, metadata)?;
+        Ok(Self { out, hasher, hash_offset, expected: metadata.length, written: 0 })
+    }
+
+    pub fn write_chunk(&mut self, values: &[f64]) -> PhiResult<()> {
+        self.write_bytes(&raw_bytes(values))
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> PhiResult<()> {
+        let count = bytes.len() / 8;
+        if self.written + count > self.expected {
+            return Err(PhiError::Validation(format!("bundle holds only {} values", self.expected)));
+        }
+        self.hasher.update(bytes);
+        self.out.write_all(bytes)?;
+        self.written += count;
+        Ok(())
+    }
+
+    /// Fill in the content hash and flush the file, failing if fewer values were written
+    /// than the metadata records
+    pub fn finish(mut self) -> PhiResult<()> {
+        if self.written != self.expected {
+            return Err(PhiError::Validation(format!(
+                "metadata says length={} but {} values were written",
+                self.expected, self.written
+            )));
+        }
+        self.out.seek(SeekFrom::Start(self.hash_offset))?;
+        self.out.write_all(format!("{:016x}", self.hasher.finish()).as_bytes())?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// Writer that hashes everything passing through it
+struct HashingWriter<W: Write> {
+    inner: W,
+    hasher: Fnv1a64,
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl PhiMemoryStore {
+    /// Export an entry as a binary bundle without loading it into memory
+    ///
+    /// Produces the same file as `PhiBundle::from_store(..).save_binary(..)`, reading the
+    /// payload once, a frame at a time. Encrypted stores are not supported.
+    pub fn export_bundle_stream<P: AsRef<Path>>(&self, name: &str, path: P) -> PhiResult<()> {
+        self.check_streamable()?;
+        let file = File::open(self.existing_data_path(name)?)?;
+        let file_len = file.metadata()?.len();
+        let mut payload = PayloadReader::new(BufReader::new(file), file_len)?;
+        let metadata = self.read_meta(name)?;
+        metadata.validate_length(payload.len())?;
+
+        let mut writer = BundleWriter::create(path, name, &metadata)?;
+        while let Some(bytes) = payload.next_bytes()? {
+            writer.write_bytes(&bytes)?;
+        }
+        writer.finish()
+    }
+
+    /// Import a binary bundle chunk by chunk, with the checks and policies of `PhiBundle::import_into`
+    ///
+    /// The payload is written with the store's compression to a temporary file first, so a
+    /// bundle that fails validation leaves the store untouched. Encrypted stores are not supported.
+    pub fn import_bundle_stream<P: AsRef<Path>>(&self, path: P, policy: ImportPolicy) -> PhiResult<ImportOutcome> {
+        self.import_from_reader(&mut BundleReader::open(path)?, policy)
+    }
+
+    /// `import_bundle_stream` for a bundle that must be signed by a trusted key; returns the
+    /// signer's label with the outcome
+    ///
+    /// The signature is checked as the payload is copied, before anything enters the store.
+    #[cfg(feature = "signing")]
+    pub fn import_bundle_stream_verified<P: AsRef<Path>>(
+        &self,
+        path: P,
+        policy: ImportPolicy,
+        trusted: &TrustedKeys,
+    ) -> PhiResult<(ImportOutcome, String)> {
+        let mut reader = BundleReader::open_verified(path, trusted)?;
+        let outcome = self.import_from_reader(&mut reader, policy)?;
+        // A skipped bundle was not read yet
+        reader.finish()?;
+        Ok((outcome, reader.signer().unwrap_or_default().to_string()))
+    }
+
+    fn import_from_reader(&self, reader: &mut BundleReader, policy: ImportPolicy) -> PhiResult<ImportOutcome> {
+        self.check_streamable()?;
+        let mut issues = header_issues(reader.name(), reader.metadata());
+        if reader.metadata().length != reader.len() {
+            issues.push(BundleIssue::LengthMismatch { recorded: reader.metadata().length, actual: reader.len() });
+        }
+        BundleReport { issues }.into_result()?;
+        let Some(name) = resolve_import_name(reader.name(), self, policy)? else {
+            return Ok(ImportOutcome::Skipped);
+        };
+
+        let partial = self.base_path().join(format!("{}.partial", name));
+        let (checksum, payload_hash) = match copy_validated(reader, &partial, self.compression()) {
+            Ok(hashes) => hashes,
+            Err(err) => {
+                let _ = fs::remove_file(&partial);
+                return Err(err);
+            }
+        };
+
+        let (manifest, revision) = self.begin_write(&name)?;
+        let previous_object = self.read_ref(&name)?;
+        if self.is_content_addressed() {
+            let hash = self.store_object_file(&partial, payload_hash)?;
+            self.link_payload(&name, previous_object, &hash)?;
+        } else {
+            fs::rename(&partial, self.base_path().join(format!("{}.bin", name)))?;
+            if previous_object.is_some() {
+                self.remove_data_ref(&name)?;
+            }
+        }
+        self.finish_write(manifest, &name, revision, reader.len(), checksum, self.compression())?;
+        self.save_meta(&name, reader.metadata())?;
+        Ok(ImportOutcome::Imported(name))
+    }
+
+    /// Streaming needs payloads that can be read and written a frame at a time, which
+    /// sealed payloads cannot
+    fn check_streamable(&self) -> PhiResult<()> {
+        match self.is_encrypted() {
+            true => Err(PhiError::Unsupported("streaming bundles in and out of an encrypted store".to_string())),
+            false => Ok(()),
+        }
+    }
+}
+
+/// Copy the remaining payload of `reader` to `path` with `codec`, checking the values on the
+/// way; returns their `phi_checksum` and the `fnv1a64` of the file
+fn copy_validated(reader: &mut BundleReader, path: &Path, codec: Compression) -> PhiResult<(u64, u64)> {
+    let file = HashingWriter { inner: BufWriter::new(File::create(path)?), hasher: Fnv1a64::new() };
+    let mut out = PayloadWriter::new(file, codec, reader.len())?;
+    let mut checksum = Fnv1a64::new();
+    let (mut index, mut first_non_finite, mut non_finite) = (0, None, 0);
+    while let Some(bytes) = reader.next_bytes()? {
+        for value in bytes.chunks_exact(8) {
+            if !f64::from_le_bytes(value.try_into().unwrap()).is_finite() {
+                first_non_finite.get_or_insert(index);
+                non_finite += 1;
+            }
+            index += 1;
+        }
+        checksum.update(&bytes);
+        out.write(&bytes)?;
+    }
+    let file = out.finish()?;
+
+    let mut issues = Vec::new();
+    if let Some(first) = first_non_finite {
+        issues.push(BundleIssue::NonFinite { first, count: non_finite });
+    }
+    if let Some(recorded) = reader.metadata().checksum.filter(|c| *c != checksum.finish()) {
+        issues.push(BundleIssue::ChecksumMismatch { recorded, actual: checksum.finish() });
+    }
+    BundleReport { issues }.into_result()?;
+    Ok((checksum.finish(), file.hasher.finish()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stream_export_matches_in_memory_bundle() {
+        let store = PhiMemoryStore::new(".phi_stream_store");
+        let data: Vec<f64> = (0..2 * DEFAULT_CHUNK_LEN + 17).map(|i| (i % 5) as f64).collect();
+        store.save("long", &data).unwrap();
+        store.save_meta("long", &PhiMetadata::new(10, 0.01, data.len())).unwrap();
+
+        let path = ".phi_stream_store.phib";
+        store.export_bundle_stream("long", path).unwrap();
+        let in_memory = PhiBundle::from_store("long", &store).unwrap().to_bytes().unwrap();
+        assert_eq!(fs::read(path).unwrap(), in_memory);
+
+        let mut reader = BundleReader::open(path).unwrap().with_chunk_len(DEFAULT_CHUNK_LEN / 2);
+        let mut chunks = 0;
+        let mut values = Vec::new();
+        while let Some(chunk) = reader.next_chunk().unwrap() {
+            chunks += 1;
+            values.extend(chunk);
+        }
+        assert_eq!((chunks, values), (5, data.clone()));
+
+        let target = PhiMemoryStore::new(".phi_stream_target");
+        let outcome = target.import_bundle_stream(path, ImportPolicy::Reject).unwrap();
+        assert_eq!(outcome, ImportOutcome::Imported("long".into()));
+        assert_eq!(target.load("long").unwrap(), data);
+        assert_eq!(target.manifest().unwrap().get("long").unwrap().length, data.len());
+        assert_eq!(target.import_bundle_stream(path, ImportPolicy::Skip).unwrap(), ImportOutcome::Skipped);
+
+        // Compressed, content-addressed stores stream both ways a frame at a time
+        let packed = PhiMemoryStore::new(".phi_stream_packed")
+            .with_compression(Compression::Deflate)
+            .with_content_addressing(true);
+        packed.import_bundle_stream(path, ImportPolicy::Reject).unwrap();
+        packed.import_bundle_stream(path, ImportPolicy::Rename).unwrap();
+        assert_eq!(packed.load("long-1").unwrap(), data);
+        assert_eq!(packed.manifest().unwrap().get("long").unwrap().compression, Compression::Deflate);
+        assert_eq!(packed.dedup_report().unwrap().objects, 1);
+        let repacked = ".phi_stream_packed.phib";
+        packed.export_bundle_stream("long", repacked).unwrap();
+        assert_eq!(fs::read(repacked).unwrap(), in_memory);
+
+        fs::remove_file(path).unwrap();
+        fs::remove_file(repacked).unwrap();
+        fs::remove_dir_all(".phi_stream_store").unwrap();
+        fs::remove_dir_all(".phi_stream_target").unwrap();
+        fs::remove_dir_all(".phi_stream_packed").unwrap();
+    }
+
+    #[test]
+    fn test_stream_rejects_tampering() {
+        let path = ".phi_stream_bad.phib";
+        let mut writer = BundleWriter::create(path, "sig", &PhiMetadata::new(10, 0.01, 3)).unwrap();
+        writer.write_chunk(&[1.0, 2.0]).unwrap();
+        assert!(writer.write_chunk(&[3.0, 4.0]).is_err());
+        writer.write_chunk(&[f64::NAN]).unwrap();
+        writer.finish().unwrap();
+        // The hash filled in by `finish` is the one the complete bundle has
+        assert!(PhiBundle::load(path).unwrap().data[2].is_nan());
+
+        let store = PhiMemoryStore::new(".phi_stream_bad_store");
+        assert!(matches!(store.import_bundle_stream(path, ImportPolicy::Reject), Err(PhiError::Validation(_))));
+        assert!(!store.contains("sig").unwrap());
+        assert!(!store.base_path().join("sig.partial").exists());
+
+        PhiBundle::new("sig", PhiMetadata::new(10, 0.01, 2), vec![1.0, 2.0]).save_binary(path).unwrap();
+        let mut bytes = fs::read(path).unwrap();
+        *bytes.last_mut().unwrap() ^= 1;
+        fs::write(path, bytes).unwrap();
+        assert!(matches!(store.import_bundle_stream(path, ImportPolicy::Reject), Err(PhiError::Validation(_))));
+
+        fs::remove_file(path).unwrap();
+        fs::remove_dir_all(".phi_stream_bad_store").unwrap();
+    }
+}
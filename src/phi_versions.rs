@@ -0,0 +1,372 @@
+//! phi_versions: revision history and store-wide snapshots for φ-memories
+//! Lets a bad re-encode be rolled back instead of overwriting data for good
+
+use crate::phi_error::{PhiError, PhiResult};
+use crate::phi_compress::{decode_payload, payload_len};
+use crate::phi_fs::PhiMemoryStore;
+use crate::phi_index::MANIFEST_FILE;
+use crate::phi_meta::{MetaFormat, PhiMetadata};
+use chrono::{DateTime, Utc};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Directory (inside the store) holding archived revisions
+pub const VERSIONS_DIR: &str = ".versions";
+/// Directory (inside the store) holding named snapshots
+pub const SNAPSHOTS_DIR: &str = ".snapshots";
+/// Snapshot files being copied in by `restore_snapshot`
+const RESTORE_STAGING_DIR: &str = ".restore-staging";
+/// Files `restore_snapshot` moved aside until the restored ones are in place
+const RESTORE_REPLACED_DIR: &str = ".restore-replaced";
+
+/// One revision of a named φ-memory
+#[derive(Debug, Clone, PartialEq)]
+pub struct Revision {
+    pub rev: u32,
+    pub length: usize,
+    pub saved_at: Option<DateTime<Utc>>,
+    pub current: bool,
+}
+
+fn is_entry_file(file_name: &str) -> bool {
+    file_name.ends_with(".bin") || MetaFormat::strip(file_name).is_some() || file_name == MANIFEST_FILE
+}
+
+/// Names of the files directly inside `dir` that satisfy `keep`
+fn files_in(dir: &Path, keep: impl Fn(&str) -> bool) -> PhiResult<Vec<String>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        if entry.file_type()?.is_file() && keep(&file_name) {
+            files.push(file_name);
+        }
+    }
+    Ok(files)
+}
+
+/// Copy every file of `from` into a fresh `to`, removing `to` again if any copy fails
+fn copy_files(from: &Path, to: &Path) -> PhiResult<()> {
+    if to.exists() {
+        fs::remove_dir_all(to)?;
+    }
+    fs::create_dir_all(to)?;
+    let copied = fs::read_dir(from)?.try_for_each(|entry| {
+        let path = entry?.path();
+        fs::copy(&path, to.join(path.file_name().unwrap_or_default()))?;
+        Ok(())
+    });
+    if copied.is_err() {
+        let _ = fs::remove_dir_all(to);
+    }
+    copied
+}
+
+/// Move `files` from one directory to another; returns the ones moved before any failure
+fn move_files(files: &[String], from: &Path, to: &Path) -> (Vec<String>, PhiResult<()>) {
+    let mut moved = Vec::new();
+    for file in files {
+        if let Err(err) = fs::rename(from.join(file), to.join(file)) {
+            return (moved, Err(err.into()));
+        }
+        moved.push(file.clone());
+    }
+    (moved, Ok(()))
+}
+
+impl PhiMemoryStore {
+    pub(crate) fn versions_dir(&self, name: &str) -> PathBuf {
+        self.base_path().join(VERSIONS_DIR).join(name)
+    }
+
+    fn snapshot_dir(&self, label: &str) -> PathBuf {
+        self.base_path().join(SNAPSHOTS_DIR).join(label)
+    }
+
+    /// Archived revision numbers of an entry, oldest first
+    fn archived_revisions(&self, name: &str) -> PhiResult<Vec<u32>> {
+        let dir = self.versions_dir(name);
+        let mut revs = Vec::new();
+        if dir.is_dir() {
+            for entry in fs::read_dir(dir)? {
+                let file_name = entry?.file_name().to_string_lossy().to_string();
+                if let Some(rev) = file_name.strip_suffix(".bin").and_then(|r| r.parse().ok()) {
+                    revs.push(rev);
+                }
+            }
+        }
+        revs.sort_unstable();
+        Ok(revs)
+    }
+
+    /// Revision number following the newest archived one
+    pub(crate) fn next_archived_revision(&self, name: &str) -> PhiResult<u32> {
+        Ok(self.archived_revisions(name)?.last().map_or(1, |rev| rev + 1))
+    }
+
+    /// Copy the current data and metadata of an entry into its history
+    pub(crate) fn archive_revision(&self, name: &str, rev: u32) -> PhiResult<()> {
+        let dir = self.versions_dir(name);
+        fs::create_dir_all(&dir)?;
+        fs::copy(self.data_path(name)?, dir.join(format!("{}.bin", rev)))?;
+        if let Some(format) = PhiMetadata::detect_format(name, self.base_path()) {
+            fs::copy(format.path(name, self.base_path()), format.path(&rev.to_string(), &dir))?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn remove_history(&self, name: &str) -> PhiResult<()> {
+        let dir = self.versions_dir(name);
+        if dir.exists() {
+            fs::remove_dir_all(dir)?;
+        }
+        Ok(())
+    }
+
+    /// All revisions of an entry, oldest first; the last one is the current data
+    pub fn history(&self, name: &str) -> PhiResult<Vec<Revision>> {
+        let dir = self.versions_dir(name);
+        let mut history = Vec::new();
+        for rev in self.archived_revisions(name)? {
+            history.push(Revision {
+                rev,
+                length: payload_len(&self.read_payload(name, &dir.join(format!("{}.bin", rev)))?),
+                saved_at: self.read_meta_in(name, &dir, &rev.to_string()).ok().map(|(m, _)| m.saved_at),
+                current: false,
+            });
+        }
+
+        let path = self.data_path(name)?;
+        if path.exists() {
+            let rev = self
+                .manifest()?
+                .get(name)
+                .map(|e| e.revision)
+                .filter(|&rev| rev > 0)
+                .map_or_else(|| self.next_archived_revision(name), Ok)?;
+            history.push(Revision {
+                rev,
+                length: payload_len(&self.read_payload(name, &path)?),
+                saved_at: self.read_meta(name).ok().map(|m| m.saved_at),
+                current: true,
+            });
+        }
+        Ok(history)
+    }
+
+    /// Load data of a specific revision
+    pub fn load_version(&self, name: &str, rev: u32) -> PhiResult<Vec<f64>> {
+        let path = self.versions_dir(name).join(format!("{}.bin", rev));
+        if path.exists() {
+            return decode_payload(&self.read_payload(name, &path)?);
+        }
+        match self.history(name)?.last() {
+            Some(current) if current.rev == rev => self.load(name),
+            _ => Err(PhiError::NotFound(format!("'{}' has no revision {}", name, rev))),
+        }
+    }
+
+    /// Load metadata of a specific revision
+    pub fn load_version_meta(&self, name: &str, rev: u32) -> PhiResult<PhiMetadata> {
+        let dir = self.versions_dir(name);
+        if dir.join(format!("{}.bin", rev)).exists() {
+            return Ok(self.read_meta_in(name, &dir, &rev.to_string())?.0);
+        }
+        match self.history(name)?.last() {
+            Some(current) if current.rev == rev => self.read_meta(name),
+            _ => Err(PhiError::NotFound(format!("'{}' has no revision {}", name, rev))),
+        }
+    }
+
+    /// Make an old revision current again (saved as a new revision)
+    pub fn rollback(&self, name: &str, rev: u32) -> PhiResult<()> {
+        let data = self.load_version(name, rev)?;
+        let meta = self.load_version_meta(name, rev).ok();
+        self.save(name, &data)?;
+        match meta {
+            Some(meta) => self.save_meta(name, &meta),
+            None => Ok(()),
+        }
+    }
+
+    /// Drop all but the `keep` newest archived revisions; returns how many were removed
+    pub fn prune_versions(&self, name: &str, keep: usize) -> PhiResult<usize> {
+        let revs = self.archived_revisions(name)?;
+        let excess = revs.len().saturating_sub(keep);
+        let dir = self.versions_dir(name);
+        for rev in &revs[..excess] {
+            fs::remove_file(dir.join(format!("{}.bin", rev)))?;
+            PhiMetadata::remove(&rev.to_string(), &dir)?;
+        }
+        Ok(excess)
+    }
+
+    /// Capture the current content of the whole store under a label
+    ///
+    /// Content-addressed entries are copied out in full so the snapshot stays self-contained.
+    pub fn snapshot(&self, label: &str) -> PhiResult<()> {
+        let dir = self.snapshot_dir(label);
+        if dir.exists() {
+            return Err(PhiError::Validation(format!("snapshot '{}' already exists", label)));
+        }
+        fs::create_dir_all(&dir)?;
+        for entry in fs::read_dir(self.base_path())? {
+            let path = entry?.path();
+            let file_name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+            if path.is_file() && is_entry_file(&file_name) {
+                fs::copy(&path, dir.join(file_name))?;
+            }
+        }
+        for name in self.list()? {
+            if self.read_ref(&name)?.is_some() {
+                fs::copy(self.data_path(&name)?, dir.join(format!("{}.bin", name)))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Labels of all snapshots taken in this store
+    pub fn list_snapshots(&self) -> PhiResult<Vec<String>> {
+        let dir = self.base_path().join(SNAPSHOTS_DIR);
+        let mut labels = Vec::new();
+        if dir.is_dir() {
+            for entry in fs::read_dir(dir)? {
+                labels.push(entry?.file_name().to_string_lossy().to_string());
+            }
+        }
+        labels.sort();
+        Ok(labels)
+    }
+
+    /// Replace the store content with a snapshot
+    ///
+    /// The snapshot is copied into the store before anything is replaced, so a failed copy
+    /// leaves the store as it was. On a versioned store every current entry is archived
+    /// first, so the restore can be undone with `rollback`; entries the snapshot does not
+    /// contain are removed but keep their history.
+    pub fn restore_snapshot(&self, label: &str) -> PhiResult<()> {
+        let dir = self.snapshot_dir(label);
+        if !dir.is_dir() {
+            return Err(PhiError::NotFound(format!("no snapshot named '{}'", label)));
+        }
+        let staging = self.base_path().join(RESTORE_STAGING_DIR);
+        copy_files(&dir, &staging)?;
+
+        if self.is_versioned() {
+            let manifest = self.manifest()?;
+            for name in self.list()? {
+                let rev = manifest.get(&name).map_or(0, |e| e.revision).max(1);
+                self.archive_revision(&name, rev)?;
+            }
+        }
+
+        // Swap the current files for the staged ones, putting them back if a move fails
+        let replaced_dir = self.base_path().join(RESTORE_REPLACED_DIR);
+        fs::create_dir_all(&replaced_dir)?;
+        let current = files_in(self.base_path(), |f| is_entry_file(f) || f.ends_with(".ref"))?;
+        let (replaced, mut swapped) = move_files(&current, self.base_path(), &replaced_dir);
+        if swapped.is_ok() {
+            let (moved_in, result) = move_files(&files_in(&staging, |_| true)?, &staging, self.base_path());
+            if result.is_err() {
+                for file in &moved_in {
+                    let _ = fs::remove_file(self.base_path().join(file));
+                }
+            }
+            swapped = result;
+        }
+        if let Err(err) = swapped {
+            let _ = move_files(&replaced, &replaced_dir, self.base_path());
+            let _ = fs::remove_dir_all(&staging);
+            return Err(err);
+        }
+        fs::remove_dir_all(&staging)?;
+        fs::remove_dir_all(&replaced_dir)?;
+        self.collect_objects()?;
+
+        // Restored entries continue after their newest archived revision
+        let mut manifest = self.manifest()?;
+        for (name, entry) in manifest.entries.iter_mut() {
+            entry.revision = self.next_archived_revision(name)?;
+        }
+        manifest.save(self.base_path())
+    }
+
+    /// Remove a snapshot
+    pub fn delete_snapshot(&self, label: &str) -> PhiResult<()> {
+        fs::remove_dir_all(self.snapshot_dir(label))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_versioned_history_and_rollback() {
+        let store = PhiMemoryStore::new(".phi_versions_store").with_versioning(true);
+        store.save("wave", &[1.0]).unwrap();
+        store.save("wave", &[1.0, 2.0]).unwrap();
+        store.save("wave", &[9.9, 9.9, 9.9]).unwrap();
+
+        let history = store.history("wave").unwrap();
+        let revs: Vec<u32> = history.iter().map(|r| r.rev).collect();
+        assert_eq!(revs, vec![1, 2, 3]);
+        assert!(history[2].current);
+        assert_eq!(history[1].length, 2);
+
+        assert_eq!(store.load_version("wave", 2).unwrap(), vec![1.0, 2.0]);
+        assert!(store.load_version("wave", 7).is_err());
+
+        store.rollback("wave", 2).unwrap();
+        assert_eq!(store.load("wave").unwrap(), vec![1.0, 2.0]);
+        assert_eq!(store.history("wave").unwrap().last().unwrap().rev, 4);
+
+        assert_eq!(store.prune_versions("wave", 1).unwrap(), 2);
+        let revs: Vec<u32> = store.history("wave").unwrap().iter().map(|r| r.rev).collect();
+        assert_eq!(revs, vec![3, 4]);
+
+        // Reopening the store keeps recording history
+        let reopened = PhiMemoryStore::new(".phi_versions_store");
+        assert!(reopened.is_versioned());
+        reopened.save("wave", &[5.0]).unwrap();
+        assert_eq!(reopened.history("wave").unwrap().len(), 3);
+
+        fs::remove_dir_all(".phi_versions_store").unwrap();
+    }
+
+    #[test]
+    fn test_snapshot_restore() {
+        let store = PhiMemoryStore::new(".phi_snapshot_store").with_versioning(true);
+        store.save("a", &[1.0]).unwrap();
+        store.snapshot("before").unwrap();
+
+        store.save("a", &[2.0]).unwrap();
+        store.save("b", &[3.0]).unwrap();
+        store.restore_snapshot("before").unwrap();
+
+        assert_eq!(store.list().unwrap(), vec!["a"]);
+        assert_eq!(store.load("a").unwrap(), vec![1.0]);
+        assert_eq!(store.list_snapshots().unwrap(), vec!["before"]);
+
+        // The overwritten and the dropped state stay reachable through history
+        let revs: Vec<u32> = store.history("a").unwrap().iter().map(|r| r.rev).collect();
+        assert_eq!(revs, vec![1, 2, 3]);
+        assert_eq!(store.load_version("a", 2).unwrap(), vec![2.0]);
+        let history = store.history("b").unwrap();
+        assert_eq!(history.len(), 1);
+        assert!(!history[0].current);
+        store.rollback("b", history[0].rev).unwrap();
+        assert_eq!(store.load("b").unwrap(), vec![3.0]);
+        store.delete("b").unwrap();
+
+        // A snapshot that cannot be copied leaves the store untouched
+        store.save("c", &[4.0]).unwrap();
+        fs::create_dir(".phi_snapshot_store/.snapshots/before/broken.bin").unwrap();
+        assert!(store.restore_snapshot("before").is_err());
+        assert_eq!(store.list().unwrap(), vec!["a", "c"]);
+        assert_eq!(store.load("c").unwrap(), vec![4.0]);
+
+        fs::remove_dir_all(".phi_snapshot_store").unwrap();
+    }
+}
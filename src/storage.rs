@@ -1,31 +1,31 @@
 //! φ-storage module: save/load quantized φ-memory to/from file
 
+use crate::phi_compress::{decode_payload, encode_payload, Compression};
+use crate::phi_error::PhiResult;
 use std::fs::File;
-use std::io::{BufReader, BufWriter, Write, Read};
+use std::io::{BufWriter, Write};
 use std::path::Path;
 
-/// Save a vector of quantized φ-values to a binary file
-pub fn save_quantized<P: AsRef<Path>>(data: &[f64], path: P) -> std::io::Result<()> {
+/// Save a vector of quantized φ-values to a binary file, as raw little-endian f64
+pub fn save_quantized<P: AsRef<Path>>(data: &[f64], path: P) -> PhiResult<()> {
     let file = File::create(path)?;
     let mut writer = BufWriter::new(file);
     for &val in data {
         writer.write_all(&val.to_le_bytes())?;
     }
+    writer.flush()?;
     Ok(())
 }
 
-/// Load a vector of quantized φ-values from a binary file
-pub fn load_quantized<P: AsRef<Path>>(path: P) -> std::io::Result<Vec<f64>> {
-    let file = File::open(path)?;
-    let mut reader = BufReader::new(file);
-    let mut buf = [0u8; 8];
-    let mut data = Vec::new();
+/// Save a vector of quantized φ-values to a compressed binary file, behind a header naming the codec
+pub fn save_quantized_compressed<P: AsRef<Path>>(data: &[f64], path: P, compression: Compression) -> PhiResult<()> {
+    std::fs::write(path, encode_payload(data, compression)?)?;
+    Ok(())
+}
 
-    while reader.read_exact(&mut buf).is_ok() {
-        let val = f64::from_le_bytes(buf);
-        data.push(val);
-    }
-    Ok(data)
+/// Load a vector of quantized φ-values from a binary file, raw or with a codec header
+pub fn load_quantized<P: AsRef<Path>>(path: P) -> PhiResult<Vec<f64>> {
+    decode_payload(&std::fs::read(path)?)
 }
 
 #[cfg(test)]
@@ -39,6 +39,7 @@ mod tests {
         let original = vec![-1.0, 0.0, 0.5, 1.0, 123.456];
 
         save_quantized(&original, path).expect("save failed");
+        assert_eq!(fs::metadata(path).unwrap().len(), original.len() as u64 * 8);
         let loaded = load_quantized(path).expect("load failed");
 
         fs::remove_file(path).unwrap();
@@ -48,4 +49,30 @@ mod tests {
             assert!((a - b).abs() < 1e-12, "a = {}, b = {}", a, b);
         }
     }
+
+    #[test]
+    fn test_raw_values_that_look_like_a_header() {
+        let path = "test_quantized_lookalike.bin";
+        let original = vec![f64::from_le_bytes(*b"PHIZ\x00\x00\x08\x00"), f64::from_bits(8), 1.0];
+
+        save_quantized(&original, path).expect("save failed");
+        let loaded = load_quantized(path).expect("load failed");
+
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(original, loaded);
+    }
+
+    #[test]
+    fn test_save_load_compressed() {
+        let path = "test_quantized_compressed.bin";
+        let original = vec![0.25; 64];
+
+        save_quantized_compressed(&original, path, Compression::Deflate).expect("save failed");
+        let loaded = load_quantized(path).expect("load failed");
+
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(original, loaded);
+    }
 }
\ No newline at end of file